@@ -0,0 +1,486 @@
+//! Derive macros for `cougr-core`.
+//!
+//! `#[derive(Component)]`, `#[derive(Resource)]` and `#[derive(Event)]`
+//! generate the matching `cougr_core` trait impl. Every field is encoded in
+//! declaration order with `cougr_core::codec`, and decoding rejects short or
+//! trailing input instead of panicking. `#[derive(Codec)]` implements
+//! `Encode`/`Decode` so a struct can be nested inside another.
+//!
+//! Fields use their `Encode`/`Decode` impl unless annotated with
+//! `#[codec(varint)]` or `#[codec(bits = N)]`. A bit-packed field stores only
+//! the low `N` bits of its value; wider values are truncated when encoded.
+//! `N` may not exceed the width of an unsigned integer field type.
+//!
+//! ```ignore
+//! #[derive(Clone, Component)]
+//! #[component(name = "powerup", storage = "sparse")]
+//! pub struct PowerUp {
+//!     #[codec(bits = 4)]
+//!     pub kind: u8,
+//!     #[codec(varint)]
+//!     pub ticks_left: u32,
+//! }
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Field, Fields, LitInt, LitStr, Result};
+
+/// Derive `cougr_core::component::ComponentTrait`
+///
+/// Attributes: `#[component(name = "...", storage = "table" | "sparse", version = N,
+/// durability = "persistent" | "temporary" | "instance")]`. Component names may
+/// be up to 32 characters; names over 9 are built with `Symbol::new`.
+#[proc_macro_derive(Component, attributes(component, codec))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, Kind::Component)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `cougr_core::resource::ResourceTrait`
+///
+/// Attributes: `#[resource(name = "...")]`.
+#[proc_macro_derive(Resource, attributes(resource, codec))]
+pub fn derive_resource(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, Kind::Resource)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `cougr_core::event::EventTrait`
+///
+/// Attributes: `#[event(name = "...", publish, entity = "field")]`. `publish`
+/// also publishes every sent event as a contract event, and `entity` names the
+/// integer field used as its entity topic.
+#[proc_macro_derive(Event, attributes(event, codec))]
+pub fn derive_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, Kind::Event)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `cougr_core::codec::Encode` and `cougr_core::codec::Decode`
+#[proc_macro_derive(Codec, attributes(codec))]
+pub fn derive_codec(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_codec(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Longest name a `symbol_short!` can hold
+const SHORT_SYMBOL_LEN: usize = 9;
+
+/// Longest name a `Symbol` can hold
+const SYMBOL_LEN: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Component,
+    Resource,
+    Event,
+}
+
+impl Kind {
+    fn attr_name(self) -> &'static str {
+        match self {
+            Kind::Component => "component",
+            Kind::Resource => "resource",
+            Kind::Event => "event",
+        }
+    }
+
+    /// Components are named once in the registry, so they may use long symbols
+    fn max_name_len(self) -> usize {
+        match self {
+            Kind::Component => SYMBOL_LEN,
+            Kind::Resource | Kind::Event => SHORT_SYMBOL_LEN,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Attrs {
+    name: Option<LitStr>,
+    storage: Option<LitStr>,
+    version: Option<LitInt>,
+    durability: Option<LitStr>,
+    publish: bool,
+    entity: Option<LitStr>,
+}
+
+fn parse_attrs(input: &DeriveInput, kind: Kind) -> Result<Attrs> {
+    let mut attrs = Attrs::default();
+    for attr in &input.attrs {
+        if !attr.path().is_ident(kind.attr_name()) {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                attrs.name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("storage") && kind == Kind::Component {
+                attrs.storage = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("version") && kind == Kind::Component {
+                let version: LitInt = meta.value()?.parse()?;
+                version.base10_parse::<u32>()?;
+                attrs.version = Some(version);
+                Ok(())
+            } else if meta.path.is_ident("durability") && kind == Kind::Component {
+                attrs.durability = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("publish") && kind == Kind::Event {
+                attrs.publish = true;
+                Ok(())
+            } else if meta.path.is_ident("entity") && kind == Kind::Event {
+                attrs.entity = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported attribute"))
+            }
+        })?;
+    }
+    Ok(attrs)
+}
+
+/// Resolve the type symbol, which must fit within the kind's name limit
+fn type_name(input: &DeriveInput, kind: Kind, attrs: &Attrs) -> Result<LitStr> {
+    let (name, span) = match &attrs.name {
+        Some(lit) => (lit.value(), lit.span()),
+        None => (input.ident.to_string().to_lowercase(), input.ident.span()),
+    };
+    if name.is_empty()
+        || name.len() > kind.max_name_len()
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(Error::new(
+            span,
+            format!(
+                "`{}` is not a valid symbol (1-{} chars of [a-zA-Z0-9_]); \
+                 set it with #[{}(name = \"...\")]",
+                name,
+                kind.max_name_len(),
+                kind.attr_name()
+            ),
+        ));
+    }
+    Ok(LitStr::new(&name, span))
+}
+
+fn expand(input: &DeriveInput, kind: Kind) -> Result<TokenStream2> {
+    let attrs = parse_attrs(input, kind)?;
+    let name = type_name(input, kind, &attrs)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let FieldCodec {
+        encode,
+        decode,
+        construct,
+    } = field_codec(struct_fields(input)?)?;
+
+    let symbol = if name.value().len() <= SHORT_SYMBOL_LEN {
+        quote!(::soroban_sdk::symbol_short!(#name))
+    } else {
+        quote!(::soroban_sdk::Symbol::new(env, #name))
+    };
+    let (trait_path, type_fn) = match kind {
+        Kind::Component => (
+            quote!(::cougr_core::component::ComponentTrait),
+            quote! {
+                #[allow(unused_variables)]
+                fn component_type(env: &::soroban_sdk::Env) -> ::soroban_sdk::Symbol {
+                    #symbol
+                }
+            },
+        ),
+        Kind::Resource => (
+            quote!(::cougr_core::resource::ResourceTrait),
+            quote! {
+                fn resource_type() -> ::soroban_sdk::Symbol {
+                    #symbol
+                }
+            },
+        ),
+        Kind::Event => (
+            quote!(::cougr_core::event::EventTrait),
+            quote! {
+                fn event_type() -> ::soroban_sdk::Symbol {
+                    #symbol
+                }
+            },
+        ),
+    };
+
+    let storage = match &attrs.storage {
+        None => quote!(),
+        Some(lit) => {
+            let variant = match lit.value().as_str() {
+                "table" => quote!(Table),
+                "sparse" => quote!(Sparse),
+                _ => {
+                    return Err(Error::new(
+                        lit.span(),
+                        "storage must be \"table\" or \"sparse\"",
+                    ))
+                }
+            };
+            quote! {
+                fn default_storage() -> ::cougr_core::component::ComponentStorage {
+                    ::cougr_core::component::ComponentStorage::#variant
+                }
+            }
+        }
+    };
+
+    let version = match &attrs.version {
+        None => quote!(),
+        Some(version) => quote! {
+            fn schema_version() -> u32 {
+                #version
+            }
+        },
+    };
+
+    let durability = match &attrs.durability {
+        None => quote!(),
+        Some(lit) => {
+            let variant = match lit.value().as_str() {
+                "persistent" => quote!(Persistent),
+                "temporary" => quote!(Temporary),
+                "instance" => quote!(Instance),
+                _ => {
+                    return Err(Error::new(
+                        lit.span(),
+                        "durability must be \"persistent\", \"temporary\" or \"instance\"",
+                    ))
+                }
+            };
+            quote! {
+                fn durability() -> ::cougr_core::component::Durability {
+                    ::cougr_core::component::Durability::#variant
+                }
+            }
+        }
+    };
+
+    let publish = if attrs.publish {
+        quote! {
+            fn published() -> bool {
+                true
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    let entity = match &attrs.entity {
+        None => quote!(),
+        Some(lit) => {
+            let field = lit.parse::<syn::Ident>()?;
+            quote! {
+                fn entity(&self) -> ::core::option::Option<u64> {
+                    ::core::option::Option::Some(self.#field as u64)
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics #trait_path for #ident #ty_generics #where_clause {
+            #type_fn
+
+            fn serialize(&self, env: &::soroban_sdk::Env) -> ::soroban_sdk::Bytes {
+                let mut __writer = ::cougr_core::codec::BytesWriter::new(env);
+                {
+                    #[allow(unused_variables)]
+                    let writer = &mut __writer;
+                    #encode
+                }
+                __writer.into_bytes()
+            }
+
+            fn deserialize(
+                _env: &::soroban_sdk::Env,
+                data: &::soroban_sdk::Bytes,
+            ) -> ::core::option::Option<Self> {
+                #[allow(unused_variables)]
+                let decode = |reader: &mut ::cougr_core::codec::BytesReader| -> ::core::result::Result<
+                    Self,
+                    ::cougr_core::codec::DecodeError,
+                > {
+                    #decode
+                    ::core::result::Result::Ok(#construct)
+                };
+                let mut reader = ::cougr_core::codec::BytesReader::new(data);
+                let value = decode(&mut reader).ok()?;
+                reader.finish().ok()?;
+                ::core::option::Option::Some(value)
+            }
+
+            #storage
+            #version
+            #durability
+            #publish
+            #entity
+        }
+    })
+}
+
+fn expand_codec(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let FieldCodec {
+        encode,
+        decode,
+        construct,
+    } = field_codec(struct_fields(input)?)?;
+
+    Ok(quote! {
+        impl #impl_generics ::cougr_core::codec::Encode for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn encode(&self, writer: &mut ::cougr_core::codec::BytesWriter) {
+                #encode
+            }
+        }
+
+        impl #impl_generics ::cougr_core::codec::Decode for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn decode(
+                reader: &mut ::cougr_core::codec::BytesReader,
+            ) -> ::core::result::Result<Self, ::cougr_core::codec::DecodeError> {
+                #decode
+                ::core::result::Result::Ok(#construct)
+            }
+        }
+    })
+}
+
+fn struct_fields(input: &DeriveInput) -> Result<&Fields> {
+    match &input.data {
+        Data::Struct(data) => Ok(&data.fields),
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            "cougr derives only support structs",
+        )),
+    }
+}
+
+/// How a single field is laid out
+enum FieldMode {
+    Default,
+    VarInt,
+    Bits(LitInt),
+}
+
+fn field_mode(field: &Field) -> Result<FieldMode> {
+    let mut mode = FieldMode::Default;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("codec") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("varint") {
+                mode = FieldMode::VarInt;
+                Ok(())
+            } else if meta.path.is_ident("bits") {
+                let bits: LitInt = meta.value()?.parse()?;
+                match bits.base10_parse::<u32>()? {
+                    1..=64 => {
+                        mode = FieldMode::Bits(bits);
+                        Ok(())
+                    }
+                    _ => Err(Error::new(bits.span(), "bits must be between 1 and 64")),
+                }
+            } else {
+                Err(meta.error("expected `varint` or `bits = N`"))
+            }
+        })?;
+    }
+    Ok(mode)
+}
+
+/// Reject `bits = N` wider than an unsigned integer field can hold
+fn check_bit_width(ty: &syn::Type, bits: &LitInt) -> Result<()> {
+    let width = match ty {
+        syn::Type::Path(path) if path.path.is_ident("u8") => 8,
+        syn::Type::Path(path) if path.path.is_ident("u16") => 16,
+        syn::Type::Path(path) if path.path.is_ident("u32") => 32,
+        _ => return Ok(()),
+    };
+    if bits.base10_parse::<u32>()? > width {
+        return Err(Error::new(
+            bits.span(),
+            format!("bits must be at most {} for this field type", width),
+        ));
+    }
+    Ok(())
+}
+
+/// Statements that encode from `self` into `writer`, decode from `reader`
+/// into `__fieldN` bindings, and the expression rebuilding `Self`
+struct FieldCodec {
+    encode: TokenStream2,
+    decode: TokenStream2,
+    construct: TokenStream2,
+}
+
+fn field_codec(fields: &Fields) -> Result<FieldCodec> {
+    let codec = quote!(::cougr_core::codec);
+    let mut writes = Vec::new();
+    let mut reads = Vec::new();
+    let mut bindings = Vec::new();
+
+    for (i, f) in fields.iter().enumerate() {
+        let ty = &f.ty;
+        let binding = format_ident!("__field{}", i);
+        let access = match &f.ident {
+            Some(ident) => quote!(self.#ident),
+            None => {
+                let index = syn::Index::from(i);
+                quote!(self.#index)
+            }
+        };
+        let (write, read) = match field_mode(f)? {
+            FieldMode::Default => (
+                quote!(#codec::Encode::encode(&#access, writer);),
+                quote!(<#ty as #codec::Decode>::decode(reader)?),
+            ),
+            FieldMode::VarInt => (
+                quote!(#codec::VarInt::write_var(&#access, writer);),
+                quote!(<#ty as #codec::VarInt>::read_var(reader)?),
+            ),
+            FieldMode::Bits(bits) => {
+                check_bit_width(ty, &bits)?;
+                (
+                    quote!(#codec::BitPacked::write_bits(&#access, writer, #bits);),
+                    quote!(<#ty as #codec::BitPacked>::read_bits(reader, #bits)?),
+                )
+            }
+        };
+        writes.push(write);
+        reads.push(quote!(let #binding = #read;));
+        bindings.push(binding);
+    }
+
+    let construct = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| f.ident.as_ref().unwrap());
+            quote!(Self { #(#names: #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(Self(#(#bindings),*)),
+        Fields::Unit => quote!(Self),
+    };
+
+    Ok(FieldCodec {
+        encode: quote!(#(#writes)*),
+        decode: quote!(#(#reads)*),
+        construct,
+    })
+}
@@ -0,0 +1,162 @@
+//! Structural change versions, used to tell when cached query results are stale.
+//!
+//! Every spawn, despawn, component insert, component removal and in-place
+//! component write bumps the world's change version, stamps the affected
+//! component types with it and records the entity in a short log. A cached
+//! query compares the versions of the types it depends on against the version
+//! it last ran at, and replays the log to patch its results instead of running
+//! again from scratch.
+
+use crate::component::ComponentMask;
+use crate::entity::EntityId;
+use alloc::vec::Vec as StdVec;
+
+/// Number of changes kept for incremental query updates
+///
+/// A cached query that fell further behind than this runs again in full.
+pub const CHANGE_LOG_CAPACITY: usize = 64;
+
+/// Change versions of a world's entities and component types
+#[derive(Debug, Clone, Default)]
+pub struct ChangeTracker {
+    version: u64,
+    /// Version of the last spawn or despawn
+    entities_version: u64,
+    /// Version of the last change to each component bit
+    component_versions: StdVec<u64>,
+    /// `(version, entity)` of the most recent changes, oldest first
+    log: StdVec<(u64, EntityId)>,
+    /// Changes up to this version may have been dropped from the log
+    truncated: u64,
+}
+
+impl ChangeTracker {
+    /// Create a tracker at version 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the version of the most recent change
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Get the version of the most recent spawn or despawn
+    pub fn entities_version(&self) -> u64 {
+        self.entities_version
+    }
+
+    /// Get the version of the most recent change to a component bit
+    pub fn component_version(&self, bit: u32) -> u64 {
+        self.component_versions
+            .get(bit as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Check if any component in `mask` changed after `version`
+    pub fn changed_since(&self, mask: ComponentMask, version: u64) -> bool {
+        bits(mask).any(|bit| self.component_version(bit) > version)
+    }
+
+    /// Get the entities changed after `version`, oldest first
+    ///
+    /// Returns `None` if some of those changes have already left the log.
+    /// Entities changed more than once appear more than once.
+    pub fn entities_since(&self, version: u64) -> Option<impl Iterator<Item = EntityId> + '_> {
+        if version < self.truncated {
+            return None;
+        }
+        let start = self.log.partition_point(|(logged, _)| *logged <= version);
+        Some(self.log[start..].iter().map(|(_, entity_id)| *entity_id))
+    }
+
+    /// Record a newly spawned entity with the components in `mask`
+    pub fn spawn(&mut self, entity_id: EntityId, mask: ComponentMask) {
+        self.record(entity_id, mask);
+        self.entities_version = self.version;
+    }
+
+    /// Record a despawned entity that had the components in `mask`
+    pub fn despawn(&mut self, entity_id: EntityId, mask: ComponentMask) {
+        self.record(entity_id, mask);
+        self.entities_version = self.version;
+    }
+
+    /// Record the components in `mask` being inserted, removed or written on an entity
+    pub fn record(&mut self, entity_id: EntityId, mask: ComponentMask) {
+        self.version += 1;
+        for bit in bits(mask) {
+            let bit = bit as usize;
+            if self.component_versions.len() <= bit {
+                self.component_versions.resize(bit + 1, 0);
+            }
+            self.component_versions[bit] = self.version;
+        }
+        if self.log.len() == CHANGE_LOG_CAPACITY {
+            let (dropped, _) = self.log.remove(0);
+            self.truncated = dropped;
+        }
+        self.log.push((self.version, entity_id));
+    }
+
+    /// Record every entity being removed at once
+    ///
+    /// Every component type counts as changed, and the log is dropped so
+    /// cached queries run again in full.
+    pub fn clear(&mut self) {
+        self.version += 1;
+        self.entities_version = self.version;
+        self.component_versions.fill(self.version);
+        self.log.clear();
+        self.truncated = self.version;
+    }
+}
+
+/// Iterate over the set bits of a mask
+fn bits(mask: ComponentMask) -> impl Iterator<Item = u32> {
+    let bits = mask.bits();
+    (0..u128::BITS).filter(move |bit| bits & (1 << bit) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_versions() {
+        let mut changes = ChangeTracker::new();
+        let position = ComponentMask::new().with(0);
+        let velocity = ComponentMask::new().with(3);
+        let a = EntityId::new(1, 0);
+        let b = EntityId::new(2, 0);
+
+        changes.spawn(a, ComponentMask::new());
+        changes.record(a, position);
+        changes.spawn(b, ComponentMask::new());
+        let seen = changes.version();
+        changes.record(b, velocity);
+
+        assert_eq!(changes.version(), 4);
+        assert_eq!(changes.entities_version(), 3);
+        assert_eq!(changes.component_version(0), 2);
+        assert_eq!(changes.component_version(3), 4);
+        assert!(!changes.changed_since(position, seen));
+        assert!(changes.changed_since(position.with(3), seen));
+        let since: StdVec<_> = changes.entities_since(seen).unwrap().collect();
+        assert_eq!(since, [b]);
+
+        // Changes older than the log's capacity can no longer be replayed
+        for _ in 0..CHANGE_LOG_CAPACITY {
+            changes.record(a, position);
+        }
+        assert!(changes.entities_since(seen).is_none());
+        assert_eq!(
+            changes
+                .entities_since(changes.version() - 1)
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,703 @@
+use soroban_sdk::xdr::{FromXdr, ToXdr};
+use soroban_sdk::{Address, Bytes, Env, Symbol};
+
+/// Why decoding failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The input ended before the value was complete
+    UnexpectedEnd,
+    /// A bool byte was neither 0 nor 1
+    InvalidBool,
+    /// A varint ran past 10 bytes or overflowed its target type
+    InvalidVarint,
+    /// A bit-packed value does not fit its target type
+    InvalidBits,
+    /// An enum or option tag had an unknown value
+    InvalidTag,
+    /// Symbol bytes were too long or contained illegal characters
+    InvalidSymbol,
+    /// Address bytes were not a recognised XDR address
+    InvalidAddress,
+    /// Bytes were left over after the value was decoded
+    TrailingBytes,
+}
+
+/// A decoding failure and the byte offset where it happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub offset: u32,
+    pub kind: DecodeErrorKind,
+}
+
+impl DecodeError {
+    pub fn new(offset: u32, kind: DecodeErrorKind) -> Self {
+        Self { offset, kind }
+    }
+}
+
+/// Appends encoded values to a `soroban_sdk::Bytes`
+///
+/// Fixed-width integers are big-endian. Bit-packed fields share bytes until
+/// the next byte-aligned write, which pads the partial byte with zeros.
+pub struct BytesWriter {
+    bytes: Bytes,
+    bit_buffer: u8,
+    bit_count: u32,
+}
+
+impl BytesWriter {
+    /// Create an empty writer
+    pub fn new(env: &Env) -> Self {
+        Self {
+            bytes: Bytes::new(env),
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    /// Get the environment the output lives on
+    pub fn env(&self) -> &Env {
+        self.bytes.env()
+    }
+
+    /// Flush any pending bits and return the encoded bytes
+    pub fn into_bytes(mut self) -> Bytes {
+        self.align();
+        self.bytes
+    }
+
+    /// Encode any [`Encode`] value
+    pub fn write<T: Encode + ?Sized>(&mut self, value: &T) {
+        value.encode(self);
+    }
+
+    /// Write a single byte
+    pub fn write_u8(&mut self, value: u8) {
+        self.align();
+        self.bytes.push_back(value);
+    }
+
+    /// Write raw bytes with no length prefix
+    pub fn write_raw(&mut self, bytes: &Bytes) {
+        self.align();
+        self.bytes.append(bytes);
+    }
+
+    /// Write an unsigned LEB128 varint
+    pub fn write_var_u64(&mut self, mut value: u64) {
+        self.align();
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push_back(byte);
+                return;
+            }
+            self.bytes.push_back(byte | 0x80);
+        }
+    }
+
+    /// Write a zigzag-encoded signed varint
+    pub fn write_var_i64(&mut self, value: i64) {
+        self.write_var_u64(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    /// Write the low `bits` bits of `value`, most significant bit first
+    ///
+    /// Higher bits of `value` are dropped, so a value wider than `bits`
+    /// reads back truncated. Panics if `bits` is more than 64.
+    pub fn write_bits(&mut self, value: u64, bits: u32) {
+        assert!(bits <= 64, "cannot pack more than 64 bits");
+        for i in (0..bits).rev() {
+            let bit = ((value >> i) & 1) as u8;
+            self.bit_buffer |= bit << (7 - self.bit_count);
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bytes.push_back(self.bit_buffer);
+                self.bit_buffer = 0;
+                self.bit_count = 0;
+            }
+        }
+    }
+
+    fn align(&mut self) {
+        if self.bit_count > 0 {
+            self.bytes.push_back(self.bit_buffer);
+            self.bit_buffer = 0;
+            self.bit_count = 0;
+        }
+    }
+}
+
+/// Reads values written by [`BytesWriter`]
+pub struct BytesReader<'a> {
+    data: &'a Bytes,
+    offset: u32,
+    bit_offset: u32,
+}
+
+impl<'a> BytesReader<'a> {
+    /// Start reading at the beginning of `data`
+    pub fn new(data: &'a Bytes) -> Self {
+        Self {
+            data,
+            offset: 0,
+            bit_offset: 0,
+        }
+    }
+
+    /// Get the environment the input lives on
+    pub fn env(&self) -> &Env {
+        self.data.env()
+    }
+
+    /// Current byte offset
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Number of unread bytes
+    pub fn remaining(&self) -> u32 {
+        self.data.len() - self.offset
+    }
+
+    /// Build an error at the current offset
+    pub fn error(&self, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError::new(self.offset, kind)
+    }
+
+    /// Decode any [`Decode`] value
+    pub fn read<T: Decode>(&mut self) -> Result<T, DecodeError> {
+        T::decode(self)
+    }
+
+    /// Check that every byte was consumed
+    pub fn finish(mut self) -> Result<(), DecodeError> {
+        self.align();
+        if self.offset != self.data.len() {
+            return Err(self.error(DecodeErrorKind::TrailingBytes));
+        }
+        Ok(())
+    }
+
+    /// Read a single byte
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        self.align();
+        let byte = self
+            .data
+            .get(self.offset)
+            .ok_or_else(|| self.error(DecodeErrorKind::UnexpectedEnd))?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    /// Read `N` bytes into an array
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let raw = self.read_raw(N as u32)?;
+        let mut buf = [0u8; N];
+        raw.copy_into_slice(&mut buf);
+        Ok(buf)
+    }
+
+    /// Read `len` raw bytes
+    pub fn read_raw(&mut self, len: u32) -> Result<Bytes, DecodeError> {
+        self.align();
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| self.error(DecodeErrorKind::UnexpectedEnd))?;
+        let raw = self.data.slice(self.offset..end);
+        self.offset = end;
+        Ok(raw)
+    }
+
+    /// Read an unsigned LEB128 varint
+    pub fn read_var_u64(&mut self) -> Result<u64, DecodeError> {
+        let start = self.offset;
+        let mut value: u64 = 0;
+        for i in 0..10 {
+            let byte = self.read_u8()?;
+            let chunk = (byte & 0x7f) as u64;
+            if i == 9 && chunk > 1 {
+                return Err(DecodeError::new(start, DecodeErrorKind::InvalidVarint));
+            }
+            value |= chunk << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::new(start, DecodeErrorKind::InvalidVarint))
+    }
+
+    /// Read a zigzag-encoded signed varint
+    pub fn read_var_i64(&mut self) -> Result<i64, DecodeError> {
+        let raw = self.read_var_u64()?;
+        Ok(((raw >> 1) as i64) ^ -((raw & 1) as i64))
+    }
+
+    /// Read `bits` bits, most significant bit first
+    pub fn read_bits(&mut self, bits: u32) -> Result<u64, DecodeError> {
+        assert!(bits <= 64, "cannot unpack more than 64 bits");
+        let mut value: u64 = 0;
+        for _ in 0..bits {
+            let byte = self
+                .data
+                .get(self.offset)
+                .ok_or_else(|| self.error(DecodeErrorKind::UnexpectedEnd))?;
+            let bit = (byte >> (7 - self.bit_offset)) & 1;
+            value = (value << 1) | bit as u64;
+            self.bit_offset += 1;
+            if self.bit_offset == 8 {
+                self.bit_offset = 0;
+                self.offset += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit_offset > 0 {
+            self.bit_offset = 0;
+            self.offset += 1;
+        }
+    }
+}
+
+/// A value that can be written with a [`BytesWriter`]
+pub trait Encode {
+    fn encode(&self, writer: &mut BytesWriter);
+}
+
+/// A value that can be read back with a [`BytesReader`]
+pub trait Decode: Sized {
+    fn decode(reader: &mut BytesReader) -> Result<Self, DecodeError>;
+}
+
+/// Integers that can be written as varints (zigzag for signed types)
+pub trait VarInt: Sized {
+    fn write_var(&self, writer: &mut BytesWriter);
+    fn read_var(reader: &mut BytesReader) -> Result<Self, DecodeError>;
+}
+
+/// Values that can be packed into a fixed number of bits
+pub trait BitPacked: Sized {
+    fn write_bits(&self, writer: &mut BytesWriter, bits: u32);
+    fn read_bits(reader: &mut BytesReader, bits: u32) -> Result<Self, DecodeError>;
+}
+
+/// Encode a value into a fresh `Bytes`
+pub fn to_bytes<T: Encode + ?Sized>(env: &Env, value: &T) -> Bytes {
+    let mut writer = BytesWriter::new(env);
+    value.encode(&mut writer);
+    writer.into_bytes()
+}
+
+/// Decode a value that must span all of `data`
+pub fn from_bytes<T: Decode>(data: &Bytes) -> Result<T, DecodeError> {
+    let mut reader = BytesReader::new(data);
+    let value = T::decode(&mut reader)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+macro_rules! impl_fixed_int {
+    ($($ty:ty),+) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, writer: &mut BytesWriter) {
+                    writer.align();
+                    writer.bytes.extend_from_array(&self.to_be_bytes());
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+                    Ok(<$ty>::from_be_bytes(reader.read_array()?))
+                }
+            }
+        )+
+    };
+}
+
+impl_fixed_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+macro_rules! impl_var_unsigned {
+    ($($ty:ty),+) => {
+        $(
+            impl VarInt for $ty {
+                fn write_var(&self, writer: &mut BytesWriter) {
+                    writer.write_var_u64(*self as u64);
+                }
+                fn read_var(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+                    let start = reader.offset();
+                    let raw = reader.read_var_u64()?;
+                    <$ty>::try_from(raw)
+                        .map_err(|_| DecodeError::new(start, DecodeErrorKind::InvalidVarint))
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! impl_var_signed {
+    ($($ty:ty),+) => {
+        $(
+            impl VarInt for $ty {
+                fn write_var(&self, writer: &mut BytesWriter) {
+                    writer.write_var_i64(*self as i64);
+                }
+                fn read_var(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+                    let start = reader.offset();
+                    let raw = reader.read_var_i64()?;
+                    <$ty>::try_from(raw)
+                        .map_err(|_| DecodeError::new(start, DecodeErrorKind::InvalidVarint))
+                }
+            }
+        )+
+    };
+}
+
+impl_var_unsigned!(u16, u32, u64);
+impl_var_signed!(i16, i32, i64);
+
+macro_rules! impl_bit_packed {
+    ($($ty:ty),+) => {
+        $(
+            impl BitPacked for $ty {
+                fn write_bits(&self, writer: &mut BytesWriter, bits: u32) {
+                    writer.write_bits(*self as u64, bits);
+                }
+                fn read_bits(reader: &mut BytesReader, bits: u32) -> Result<Self, DecodeError> {
+                    let start = reader.offset();
+                    let raw = reader.read_bits(bits)?;
+                    <$ty>::try_from(raw)
+                        .map_err(|_| DecodeError::new(start, DecodeErrorKind::InvalidBits))
+                }
+            }
+        )+
+    };
+}
+
+impl_bit_packed!(u8, u16, u32, u64);
+
+impl BitPacked for bool {
+    fn write_bits(&self, writer: &mut BytesWriter, bits: u32) {
+        writer.write_bits(*self as u64, bits);
+    }
+    fn read_bits(reader: &mut BytesReader, bits: u32) -> Result<Self, DecodeError> {
+        let start = reader.offset();
+        match reader.read_bits(bits)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::new(start, DecodeErrorKind::InvalidBool)),
+        }
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, writer: &mut BytesWriter) {
+        writer.write_u8(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+        match reader.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::new(
+                reader.offset() - 1,
+                DecodeErrorKind::InvalidBool,
+            )),
+        }
+    }
+}
+
+/// Varint length prefix followed by the raw bytes
+impl Encode for Bytes {
+    fn encode(&self, writer: &mut BytesWriter) {
+        writer.write_var_u64(self.len() as u64);
+        writer.write_raw(self);
+    }
+}
+
+impl Decode for Bytes {
+    fn decode(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+        let len = u32::read_var(reader)?;
+        reader.read_raw(len)
+    }
+}
+
+/// A presence byte followed by the value
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, writer: &mut BytesWriter) {
+        match self {
+            None => writer.write_u8(0),
+            Some(value) => {
+                writer.write_u8(1);
+                value.encode(writer);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+        match reader.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(reader)?)),
+            _ => Err(DecodeError::new(
+                reader.offset() - 1,
+                DecodeErrorKind::InvalidTag,
+            )),
+        }
+    }
+}
+
+/// `ScVal` discriminants and the XDR header length in front of symbol chars
+const SCV_SYMBOL: u32 = 15;
+const SCV_ADDRESS: u32 = 18;
+const SCV_HEADER_LEN: u32 = 8;
+const MAX_SYMBOL_LEN: u32 = 32;
+
+/// The symbol's characters behind a varint length, without the XDR envelope
+impl Encode for Symbol {
+    fn encode(&self, writer: &mut BytesWriter) {
+        let xdr = self.clone().to_xdr(writer.env());
+        debug_assert_eq!(u32::from_be_bytes(xdr_word(&xdr, 0)), SCV_SYMBOL);
+        let len = u32::from_be_bytes(xdr_word(&xdr, 4));
+        xdr.slice(SCV_HEADER_LEN..SCV_HEADER_LEN + len)
+            .encode(writer);
+    }
+}
+
+impl Decode for Symbol {
+    fn decode(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+        let start = reader.offset();
+        let invalid = DecodeError::new(start, DecodeErrorKind::InvalidSymbol);
+        let chars = Bytes::decode(reader)?;
+        if chars.len() > MAX_SYMBOL_LEN {
+            return Err(invalid);
+        }
+        let mut buf = [0u8; MAX_SYMBOL_LEN as usize];
+        let buf = &mut buf[..chars.len() as usize];
+        chars.copy_into_slice(buf);
+        if !buf.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_') {
+            return Err(invalid);
+        }
+        let name = core::str::from_utf8(buf).map_err(|_| invalid)?;
+        Ok(Symbol::new(reader.env(), name))
+    }
+}
+
+/// The address's XDR behind a varint length
+///
+/// The XDR header is checked before handing it to the host, so malformed
+/// input is reported as an error instead of trapping.
+impl Encode for Address {
+    fn encode(&self, writer: &mut BytesWriter) {
+        let xdr = self.clone().to_xdr(writer.env());
+        xdr.encode(writer);
+    }
+}
+
+impl Decode for Address {
+    fn decode(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+        let start = reader.offset();
+        let invalid = DecodeError::new(start, DecodeErrorKind::InvalidAddress);
+        let xdr = Bytes::decode(reader)?;
+        // ScVal::Address, then ScAddress::Contract(hash) or
+        // ScAddress::Account(PublicKey::Ed25519(key))
+        let well_formed = match xdr.len() {
+            40 => u32::from_be_bytes(xdr_word(&xdr, 4)) == 1,
+            44 => {
+                u32::from_be_bytes(xdr_word(&xdr, 4)) == 0
+                    && u32::from_be_bytes(xdr_word(&xdr, 8)) == 0
+            }
+            _ => false,
+        };
+        if !well_formed || u32::from_be_bytes(xdr_word(&xdr, 0)) != SCV_ADDRESS {
+            return Err(invalid);
+        }
+        Address::from_xdr(reader.env(), &xdr).map_err(|_| invalid)
+    }
+}
+
+fn xdr_word(xdr: &Bytes, at: u32) -> [u8; 4] {
+    let mut word = [0u8; 4];
+    xdr.slice(at..at + 4).copy_into_slice(&mut word);
+    word
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident $index:tt),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            fn encode(&self, writer: &mut BytesWriter) {
+                $(self.$index.encode(writer);)+
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode(reader: &mut BytesReader) -> Result<Self, DecodeError> {
+                Ok(($($name::decode(reader)?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A 0, B 1);
+impl_tuple!(A 0, B 1, C 2);
+impl_tuple!(A 0, B 1, C 2, D 3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soroban_sdk::{symbol_short, testutils::Address as _, Env};
+
+    #[test]
+    fn test_fixed_ints_and_bools() {
+        let env = Env::default();
+        let mut writer = BytesWriter::new(&env);
+        writer.write(&-2i32);
+        writer.write(&u64::MAX);
+        writer.write(&true);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes.len(), 13);
+
+        let mut reader = BytesReader::new(&bytes);
+        assert_eq!(reader.read::<i32>().unwrap(), -2);
+        assert_eq!(reader.read::<u64>().unwrap(), u64::MAX);
+        assert!(reader.read::<bool>().unwrap());
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn test_varints() {
+        let env = Env::default();
+        let mut writer = BytesWriter::new(&env);
+        writer.write_var_u64(300);
+        writer.write_var_i64(-1);
+        writer.write_var_u64(u64::MAX);
+        let bytes = writer.into_bytes();
+        // 2 bytes for 300, 1 for zigzag(-1), 10 for u64::MAX
+        assert_eq!(bytes.len(), 13);
+
+        let mut reader = BytesReader::new(&bytes);
+        assert_eq!(reader.read_var_u64().unwrap(), 300);
+        assert_eq!(reader.read_var_i64().unwrap(), -1);
+        assert_eq!(reader.read_var_u64().unwrap(), u64::MAX);
+
+        let overlong = Bytes::from_array(&env, &[0xff; 11]);
+        let err = BytesReader::new(&overlong).read_var_u64().unwrap_err();
+        assert_eq!(err, DecodeError::new(0, DecodeErrorKind::InvalidVarint));
+    }
+
+    #[test]
+    fn test_bit_packing() {
+        let env = Env::default();
+        let mut writer = BytesWriter::new(&env);
+        writer.write_bits(5, 3);
+        true.write_bits(&mut writer, 1);
+        writer.write_bits(9, 4);
+        writer.write(&7u8);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes, Bytes::from_array(&env, &[0b1011_1001, 7]));
+
+        let mut reader = BytesReader::new(&bytes);
+        assert_eq!(reader.read_bits(3).unwrap(), 5);
+        assert!(bool::read_bits(&mut reader, 1).unwrap());
+        assert_eq!(u8::read_bits(&mut reader, 4).unwrap(), 9);
+        assert_eq!(reader.read::<u8>().unwrap(), 7);
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn test_bit_packing_truncates() {
+        let env = Env::default();
+        let mut writer = BytesWriter::new(&env);
+        writer.write_bits(0b1_0110, 4);
+        0xabu8.write_bits(&mut writer, 4);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes, Bytes::from_array(&env, &[0b0110_1011]));
+    }
+
+    #[test]
+    fn test_symbols_and_addresses() {
+        let env = Env::default();
+        let short = symbol_short!("fire");
+        let long = Symbol::new(&env, "a_much_longer_symbol_name");
+        let address = Address::generate(&env);
+
+        let bytes = to_bytes(&env, &(short.clone(), long.clone(), address.clone()));
+        // The symbol is stored as its characters, not as XDR
+        assert_eq!(bytes.slice(0..5), Bytes::from_slice(&env, b"\x04fire"));
+
+        let decoded: (Symbol, Symbol, Address) = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, (short, long, address));
+
+        let bad_symbol = Bytes::from_slice(&env, b"\x02a-");
+        let err = from_bytes::<Symbol>(&bad_symbol).unwrap_err();
+        assert_eq!(err.kind, DecodeErrorKind::InvalidSymbol);
+
+        let bad_address = to_bytes(&env, &Bytes::from_array(&env, &[0u8; 40]));
+        let err = from_bytes::<Address>(&bad_address).unwrap_err();
+        assert_eq!(err.kind, DecodeErrorKind::InvalidAddress);
+    }
+
+    #[derive(Debug, PartialEq, cougr_derive::Codec)]
+    struct Stats {
+        #[codec(varint)]
+        hp: i32,
+        #[codec(bits = 3)]
+        class: u8,
+        #[codec(bits = 1)]
+        alive: bool,
+    }
+
+    #[derive(Debug, PartialEq, cougr_derive::Codec)]
+    struct Unit {
+        owner: Option<Symbol>,
+        stats: Stats,
+        tile: (u16, u16),
+    }
+
+    #[test]
+    fn test_derived_nested_structs() {
+        let env = Env::default();
+        let unit = Unit {
+            owner: Some(symbol_short!("red")),
+            stats: Stats {
+                hp: -3,
+                class: 5,
+                alive: true,
+            },
+            tile: (4, 9),
+        };
+
+        let bytes = to_bytes(&env, &unit);
+        // 1 + 4 (owner) + 1 (hp) + 1 (class and alive packed) + 4 (tile)
+        assert_eq!(bytes.len(), 11);
+        assert_eq!(from_bytes::<Unit>(&bytes).unwrap(), unit);
+
+        let err = from_bytes::<Unit>(&bytes.slice(0..7)).unwrap_err();
+        assert_eq!(err, DecodeError::new(7, DecodeErrorKind::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_decode_errors_report_offsets() {
+        let env = Env::default();
+        let bytes = Bytes::from_array(&env, &[0, 0, 0, 1, 2]);
+
+        let mut reader = BytesReader::new(&bytes);
+        assert_eq!(reader.read::<u32>().unwrap(), 1);
+        let err = reader.read::<bool>().unwrap_err();
+        assert_eq!(err, DecodeError::new(4, DecodeErrorKind::InvalidBool));
+
+        let err = from_bytes::<u64>(&bytes).unwrap_err();
+        assert_eq!(err, DecodeError::new(0, DecodeErrorKind::UnexpectedEnd));
+
+        let err = from_bytes::<u32>(&bytes).unwrap_err();
+        assert_eq!(err, DecodeError::new(4, DecodeErrorKind::TrailingBytes));
+    }
+}
@@ -1,7 +1,5 @@
 use alloc::vec::Vec;
-use soroban_sdk::{
-    contracttype, symbol_short, Bytes, Env, FromVal, IntoVal, Symbol, TryFromVal, Val,
-};
+use soroban_sdk::{contracttype, Bytes, Env, IntoVal, Symbol, TryFromVal, Val};
 
 /// A unique identifier for a component type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -10,12 +8,25 @@ pub struct ComponentId {
 }
 
 impl ComponentId {
+    /// Create an ID; IDs range from 1 to [`MAX_COMPONENT_TYPES`]
+    ///
+    /// Panics if `id` is out of that range; use `try_new` to check it.
     pub fn new(id: u32) -> Self {
-        Self { id }
+        Self::try_new(id).expect("component ID out of range")
+    }
+    /// Create an ID, or `None` if no registry can hand it out
+    pub fn try_new(id: u32) -> Option<Self> {
+        (1..=MAX_COMPONENT_TYPES)
+            .contains(&id)
+            .then_some(Self { id })
     }
     pub fn id(&self) -> u32 {
         self.id
     }
+    /// The bit this component occupies in a [`ComponentMask`]
+    pub fn bit(&self) -> u32 {
+        self.id - 1
+    }
 }
 // Soroban SDK trait implementations for ComponentId
 impl IntoVal<Env, Val> for ComponentId {
@@ -29,21 +40,104 @@ impl TryFromVal<Env, Val> for ComponentId {
 
     fn try_from_val(env: &Env, val: &Val) -> Result<Self, Self::Error> {
         let id: u32 = TryFromVal::try_from_val(env, val)?;
-        Ok(ComponentId::new(id))
+        ComponentId::try_new(id).ok_or(soroban_sdk::ConversionError)
+    }
+}
+
+/// Maximum number of component types a [`ComponentRegistry`] can hold,
+/// one per bit of a [`ComponentMask`]
+pub const MAX_COMPONENT_TYPES: u32 = 128;
+
+/// Bitset of component types, indexed by [`ComponentRegistry::bit_index`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ComponentMask {
+    bits: u128,
+}
+
+impl ComponentMask {
+    /// Create an empty mask
+    pub fn new() -> Self {
+        Self { bits: 0 }
+    }
+    pub fn from_bits(bits: u128) -> Self {
+        Self { bits }
+    }
+    pub fn bits(&self) -> u128 {
+        self.bits
+    }
+    /// Return a copy of this mask with `bit` set
+    pub fn with(self, bit: u32) -> Self {
+        Self {
+            bits: self.bits | (1 << bit),
+        }
+    }
+    /// Return a copy of this mask with every bit of `other` also set
+    pub fn union(self, other: ComponentMask) -> Self {
+        Self {
+            bits: self.bits | other.bits,
+        }
+    }
+    /// Return a copy of this mask with `bit` cleared
+    pub fn without(self, bit: u32) -> Self {
+        Self {
+            bits: self.bits & !(1 << bit),
+        }
+    }
+    pub fn contains(&self, bit: u32) -> bool {
+        self.bits & (1 << bit) != 0
+    }
+    /// Check if every bit of `other` is set in this mask
+    pub fn contains_all(&self, other: ComponentMask) -> bool {
+        self.bits & other.bits == other.bits
+    }
+    /// Check if this mask shares any bit with `other`
+    pub fn intersects(&self, other: ComponentMask) -> bool {
+        self.bits & other.bits != 0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+    pub fn count(&self) -> u32 {
+        self.bits.count_ones()
+    }
+}
+
+impl IntoVal<Env, Val> for ComponentMask {
+    fn into_val(&self, env: &Env) -> Val {
+        self.bits.into_val(env)
+    }
+}
+
+impl TryFromVal<Env, Val> for ComponentMask {
+    type Error = soroban_sdk::ConversionError;
+
+    fn try_from_val(env: &Env, val: &Val) -> Result<Self, Self::Error> {
+        let bits: u128 = TryFromVal::try_from_val(env, val)?;
+        Ok(ComponentMask::from_bits(bits))
     }
 }
 
 #[contracttype]
 #[repr(u32)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ComponentStorage {
+    #[default]
     Table = 0,
     Sparse = 1,
 }
-impl Default for ComponentStorage {
-    fn default() -> Self {
-        Self::Table
-    }
+
+/// Ledger storage class a component's entries live in
+///
+/// Persistent entries are archived when their TTL runs out, temporary entries
+/// are deleted, and instance entries share the contract instance's TTL.
+#[contracttype]
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    #[default]
+    Persistent = 0,
+    Temporary = 1,
+    Instance = 2,
 }
 
 #[contracttype]
@@ -52,6 +146,8 @@ pub struct Component {
     pub component_type: Symbol,
     pub data: Bytes,
     pub storage: ComponentStorage,
+    /// Schema version the data was encoded with
+    pub version: u32,
 }
 
 impl Component {
@@ -60,6 +156,7 @@ impl Component {
             component_type,
             data,
             storage: ComponentStorage::default(),
+            version: 0,
         }
     }
     pub fn with_storage(component_type: Symbol, data: Bytes, storage: ComponentStorage) -> Self {
@@ -67,6 +164,16 @@ impl Component {
             component_type,
             data,
             storage,
+            version: 0,
+        }
+    }
+    /// Build a component from a typed value, recording its storage and schema version
+    pub fn from_typed<T: ComponentTrait>(env: &Env, value: &T) -> Self {
+        Self {
+            component_type: T::component_type(env),
+            data: value.serialize(env),
+            storage: T::default_storage(),
+            version: T::schema_version(),
         }
     }
     pub fn component_type(&self) -> &Symbol {
@@ -84,6 +191,94 @@ impl Component {
     pub fn set_storage(&mut self, storage: ComponentStorage) {
         self.storage = storage;
     }
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    pub fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+}
+
+/// Upgrades component data from schema version `from` to `from + 1`
+pub type Migration = fn(u32, Bytes) -> Bytes;
+
+/// Registry of schema upgraders, keyed by component type
+///
+/// Each component type has one upgrader that is applied repeatedly until the
+/// stored data reaches the type's current schema version.
+#[derive(Debug, Clone, Default)]
+pub struct MigrationRegistry {
+    migrations: Vec<(Symbol, u32, Migration)>,
+}
+
+impl MigrationRegistry {
+    /// Create an empty migration registry
+    pub fn new() -> Self {
+        Self {
+            migrations: Vec::new(),
+        }
+    }
+
+    /// Register the upgrader for a component type, replacing any previous one
+    pub fn register(&mut self, component_type: Symbol, current_version: u32, migration: Migration) {
+        for entry in self.migrations.iter_mut() {
+            if entry.0 == component_type {
+                *entry = (component_type, current_version, migration);
+                return;
+            }
+        }
+        self.migrations
+            .push((component_type, current_version, migration));
+    }
+
+    /// Get the current schema version registered for a component type
+    pub fn current_version(&self, component_type: &Symbol) -> Option<u32> {
+        self.find(component_type).map(|(version, _)| version)
+    }
+
+    /// Check if a component was stored with an outdated schema
+    pub fn needs_migration(&self, component: &Component) -> bool {
+        match self.current_version(component.component_type()) {
+            Some(current) => component.version() < current,
+            None => false,
+        }
+    }
+
+    /// Upgrade a component to its current schema version in place
+    ///
+    /// Returns `true` if the component data changed.
+    pub fn migrate(&self, component: &mut Component) -> bool {
+        let (current, migration) = match self.find(component.component_type()) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let mut migrated = false;
+        while component.version < current {
+            component.data = migration(component.version, component.data.clone());
+            component.version += 1;
+            migrated = true;
+        }
+        migrated
+    }
+
+    /// Get the number of component types with a registered upgrader
+    pub fn len(&self) -> usize {
+        self.migrations.len()
+    }
+
+    /// Check if no upgraders are registered
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+    }
+
+    fn find(&self, component_type: &Symbol) -> Option<(u32, Migration)> {
+        for (ctype, version, migration) in &self.migrations {
+            if ctype == component_type {
+                return Some((*version, *migration));
+            }
+        }
+        None
+    }
 }
 
 /// Registry for managing component types
@@ -91,6 +286,11 @@ impl Component {
 pub struct ComponentRegistry {
     next_id: u32,
     components: Vec<(Symbol, ComponentId)>,
+    storage_kinds: Vec<(Symbol, ComponentStorage)>,
+    durabilities: Vec<(Symbol, Durability)>,
+    migrations: MigrationRegistry,
+    /// Bumped whenever a type is registered or its durability changes
+    revision: u32,
 }
 
 impl ComponentRegistry {
@@ -99,22 +299,134 @@ impl ComponentRegistry {
         Self {
             next_id: 1,
             components: Vec::new(),
+            storage_kinds: Vec::new(),
+            durabilities: Vec::new(),
+            migrations: MigrationRegistry::new(),
+            revision: 0,
+        }
+    }
+
+    /// Register a typed component along with its storage kind and durability
+    ///
+    /// Returns `None` if the registry is full.
+    pub fn register<T: ComponentTrait>(&mut self, env: &Env) -> Option<ComponentId> {
+        let component_type = T::component_type(env);
+        let id = self.register_component(component_type.clone())?;
+        self.set_storage_kind(component_type.clone(), T::default_storage());
+        self.set_durability(component_type, T::durability());
+        Some(id)
+    }
+
+    /// Get every registered name and its durability, ordered by component ID
+    ///
+    /// This is the only place component names need to be stored; storage
+    /// and entities refer to components by [`ComponentId`].
+    pub fn entries(&self, env: &Env) -> soroban_sdk::Vec<(Symbol, Durability)> {
+        let mut entries = soroban_sdk::Vec::new(env);
+        for (ctype, _) in &self.components {
+            entries.push_back((ctype.clone(), self.durability(ctype)));
+        }
+        entries
+    }
+
+    /// Register entries previously exported by [`ComponentRegistry::entries`]
+    ///
+    /// Must be called before any other registration so IDs line up.
+    pub fn register_entries(&mut self, entries: &soroban_sdk::Vec<(Symbol, Durability)>) {
+        for (name, durability) in entries.iter() {
+            if self.register_component(name.clone()).is_some() {
+                self.set_durability(name, durability);
+            }
+        }
+    }
+
+    /// Get the number of registrations and durability changes so far
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// Set the storage backend a component type is kept in
+    pub fn set_storage_kind(&mut self, component_type: Symbol, storage: ComponentStorage) {
+        for entry in self.storage_kinds.iter_mut() {
+            if entry.0 == component_type {
+                entry.1 = storage;
+                return;
+            }
+        }
+        self.storage_kinds.push((component_type, storage));
+    }
+
+    /// Get the storage backend registered for a component type
+    pub fn storage_kind(&self, component_type: &Symbol) -> Option<ComponentStorage> {
+        for (ctype, storage) in &self.storage_kinds {
+            if ctype == component_type {
+                return Some(*storage);
+            }
+        }
+        None
+    }
+
+    /// Set the ledger durability of a component type
+    pub fn set_durability(&mut self, component_type: Symbol, durability: Durability) {
+        for entry in self.durabilities.iter_mut() {
+            if entry.0 == component_type {
+                if entry.1 != durability {
+                    entry.1 = durability;
+                    self.revision += 1;
+                }
+                return;
+            }
+        }
+        self.durabilities.push((component_type, durability));
+        self.revision += 1;
+    }
+
+    /// Get the ledger durability of a component type
+    ///
+    /// Types that never declared one are persistent.
+    pub fn durability(&self, component_type: &Symbol) -> Durability {
+        for (ctype, durability) in &self.durabilities {
+            if ctype == component_type {
+                return *durability;
+            }
         }
+        Durability::default()
+    }
+
+    /// Register a schema upgrader for a component type
+    pub fn register_migration(
+        &mut self,
+        component_type: Symbol,
+        current_version: u32,
+        migration: Migration,
+    ) {
+        self.register_component(component_type.clone());
+        self.migrations
+            .register(component_type, current_version, migration);
+    }
+
+    /// Get the schema migrations registered on this registry
+    pub fn migrations(&self) -> &MigrationRegistry {
+        &self.migrations
     }
 
     /// Register a new component type
-    pub fn register_component(&mut self, component_type: Symbol) -> ComponentId {
+    ///
+    /// Returns `None` if the type is new and the registry already holds
+    /// [`MAX_COMPONENT_TYPES`] types.
+    pub fn register_component(&mut self, component_type: Symbol) -> Option<ComponentId> {
         // Check if component type is already registered
         for (ctype, id) in &self.components {
             if ctype == &component_type {
-                return *id;
+                return Some(*id);
             }
         }
 
-        let id = ComponentId::new(self.next_id);
+        let id = ComponentId::try_new(self.next_id)?;
         self.next_id += 1;
+        self.revision += 1;
         self.components.push((component_type, id));
-        id
+        Some(id)
     }
 
     /// Get the component ID for a component type
@@ -137,6 +449,22 @@ impl ComponentRegistry {
         None
     }
 
+    /// Get the bit a component type occupies in a [`ComponentMask`]
+    pub fn bit_index(&self, component_type: &Symbol) -> Option<u32> {
+        self.get_component_id(component_type).map(|id| id.bit())
+    }
+
+    /// Build the mask of a set of component types
+    ///
+    /// Returns `None` if any of the types is not registered.
+    pub fn mask_of(&self, component_types: &soroban_sdk::Vec<Symbol>) -> Option<ComponentMask> {
+        let mut mask = ComponentMask::new();
+        for component_type in component_types.iter() {
+            mask = mask.with(self.bit_index(&component_type)?);
+        }
+        Some(mask)
+    }
+
     /// Get the number of registered component types
     pub fn component_count(&self) -> usize {
         self.components.len()
@@ -160,7 +488,11 @@ impl Default for ComponentRegistry {
 }
 
 pub trait ComponentTrait {
-    fn component_type() -> Symbol;
+    /// Name of this component type
+    ///
+    /// Only stored once, in the [`ComponentRegistry`], so it may be a long
+    /// `Symbol::new` name rather than a 9-character `symbol_short!`.
+    fn component_type(env: &Env) -> Symbol;
     fn serialize(&self, env: &Env) -> Bytes;
     fn deserialize(env: &Env, data: &Bytes) -> Option<Self>
     where
@@ -168,10 +500,76 @@ pub trait ComponentTrait {
     fn default_storage() -> ComponentStorage {
         ComponentStorage::Table
     }
+    /// Version of the byte layout produced by `serialize`
+    ///
+    /// Bump this when the layout changes and register a [`Migration`] so
+    /// data stored under older versions can still be read.
+    fn schema_version() -> u32 {
+        0
+    }
+    /// Ledger storage class for this component when it is persisted
+    fn durability() -> Durability {
+        Durability::Persistent
+    }
 }
 
+/// A set of typed components that can be spawned or inserted together
+///
+/// Implemented for every [`ComponentTrait`] type and for tuples of up to
+/// eight of them, so `world.spawn_with((Position::new(0, 0), Velocity::new(1, 0)))`
+/// works without building a `Vec<Component>` by hand.
+pub trait Bundle {
+    /// Serialize the bundle into dynamic components
+    fn into_components(self, env: &Env) -> soroban_sdk::Vec<Component>;
+
+    /// Register every component type in the bundle
+    ///
+    /// Returns `false` if the registry is full and a type was left out.
+    fn register(env: &Env, registry: &mut ComponentRegistry) -> bool;
+}
+
+impl<T: ComponentTrait> Bundle for T {
+    fn register(env: &Env, registry: &mut ComponentRegistry) -> bool {
+        registry.register::<T>(env).is_some()
+    }
+
+    fn into_components(self, env: &Env) -> soroban_sdk::Vec<Component> {
+        let mut components = soroban_sdk::Vec::new(env);
+        components.push_back(Component::from_typed(env, &self));
+        components
+    }
+}
+
+macro_rules! impl_bundle_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: ComponentTrait),+> Bundle for ($($name,)+) {
+            fn register(env: &Env, registry: &mut ComponentRegistry) -> bool {
+                true $(&& registry.register::<$name>(env).is_some())+
+            }
+
+            #[allow(non_snake_case)]
+            fn into_components(self, env: &Env) -> soroban_sdk::Vec<Component> {
+                let ($($name,)+) = self;
+                let mut components = soroban_sdk::Vec::new(env);
+                $(components.push_back(Component::from_typed(env, &$name));)+
+                components
+            }
+        }
+    };
+}
+
+impl_bundle_for_tuple!(A);
+impl_bundle_for_tuple!(A, B);
+impl_bundle_for_tuple!(A, B, C);
+impl_bundle_for_tuple!(A, B, C, D);
+impl_bundle_for_tuple!(A, B, C, D, E);
+impl_bundle_for_tuple!(A, B, C, D, E, F);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H);
+
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, cougr_derive::Component)]
+#[component(name = "position")]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -181,40 +579,10 @@ impl Position {
         Self { x, y }
     }
 }
-impl ComponentTrait for Position {
-    fn component_type() -> Symbol {
-        symbol_short!("position")
-    }
-    fn serialize(&self, env: &Env) -> Bytes {
-        let mut bytes = Bytes::new(env);
-        let x_bytes = Bytes::from_array(env, &self.x.to_be_bytes());
-        let y_bytes = Bytes::from_array(env, &self.y.to_be_bytes());
-        bytes.append(&x_bytes);
-        bytes.append(&y_bytes);
-        bytes
-    }
-    fn deserialize(env: &Env, data: &Bytes) -> Option<Self> {
-        if data.len() != 8 {
-            return None;
-        }
-        let x = i32::from_be_bytes([
-            data.get(0).unwrap(),
-            data.get(1).unwrap(),
-            data.get(2).unwrap(),
-            data.get(3).unwrap(),
-        ]);
-        let y = i32::from_be_bytes([
-            data.get(4).unwrap(),
-            data.get(5).unwrap(),
-            data.get(6).unwrap(),
-            data.get(7).unwrap(),
-        ]);
-        Some(Self { x, y })
-    }
-}
 
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, cougr_derive::Component)]
+#[component(name = "velocity")]
 pub struct Velocity {
     pub x: i32,
     pub y: i32,
@@ -224,42 +592,11 @@ impl Velocity {
         Self { x, y }
     }
 }
-impl ComponentTrait for Velocity {
-    fn component_type() -> Symbol {
-        symbol_short!("velocity")
-    }
-    fn serialize(&self, env: &Env) -> Bytes {
-        let mut bytes = Bytes::new(env);
-        let x_bytes = Bytes::from_array(env, &self.x.to_be_bytes());
-        let y_bytes = Bytes::from_array(env, &self.y.to_be_bytes());
-        bytes.append(&x_bytes);
-        bytes.append(&y_bytes);
-        bytes
-    }
-    fn deserialize(env: &Env, data: &Bytes) -> Option<Self> {
-        if data.len() != 8 {
-            return None;
-        }
-        let x = i32::from_be_bytes([
-            data.get(0).unwrap(),
-            data.get(1).unwrap(),
-            data.get(2).unwrap(),
-            data.get(3).unwrap(),
-        ]);
-        let y = i32::from_be_bytes([
-            data.get(4).unwrap(),
-            data.get(5).unwrap(),
-            data.get(6).unwrap(),
-            data.get(7).unwrap(),
-        ]);
-        Some(Self { x, y })
-    }
-}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use soroban_sdk::Env;
+    use soroban_sdk::{symbol_short, Env};
 
     #[test]
     fn test_component_id_creation() {
@@ -291,7 +628,68 @@ mod tests {
         assert!(registry.is_registered(&component_type));
 
         let retrieved_id = registry.get_component_id(&component_type);
-        assert_eq!(retrieved_id, Some(id));
+        assert_eq!(retrieved_id, id);
+    }
+
+    #[test]
+    fn test_component_registry_limits() {
+        let env = Env::default();
+        let mut registry = ComponentRegistry::new();
+        for i in 0..MAX_COMPONENT_TYPES {
+            let name = Symbol::new(&env, &alloc::format!("c{}", i));
+            assert!(registry.register_component(name).is_some());
+        }
+        // A full registry refuses new types but still resolves known ones
+        assert!(registry
+            .register_component(symbol_short!("extra"))
+            .is_none());
+        assert!(registry
+            .register_component(Symbol::new(&env, "c0"))
+            .is_some());
+
+        assert!(ComponentId::try_new(0).is_none());
+        assert!(ComponentId::try_new(MAX_COMPONENT_TYPES + 1).is_none());
+        assert_eq!(ComponentId::try_new(1).unwrap().bit(), 0);
+        let entity = crate::entity::Entity::new(&env, crate::entity::EntityId::new(1, 0));
+        assert!(!entity.has_component(ComponentId::try_new(MAX_COMPONENT_TYPES).unwrap()));
+    }
+
+    #[test]
+    #[should_panic(expected = "component ID out of range")]
+    fn test_component_id_out_of_range() {
+        ComponentId::new(0);
+    }
+
+    #[derive(Clone, cougr_derive::Component)]
+    #[component(name = "powerup", storage = "sparse")]
+    struct PowerUp(#[codec(bits = 4)] u8, #[codec(bits = 1)] bool, Symbol);
+
+    #[test]
+    fn test_derived_component() {
+        let env = Env::default();
+        assert_eq!(PowerUp::component_type(&env), symbol_short!("powerup"));
+        assert_eq!(PowerUp::default_storage(), ComponentStorage::Sparse);
+        assert_eq!(Velocity::default_storage(), ComponentStorage::Table);
+
+        let power_up = PowerUp(3, true, symbol_short!("shield"));
+        let data = power_up.serialize(&env);
+        let decoded = PowerUp::deserialize(&env, &data).unwrap();
+        assert_eq!(decoded.0, 3);
+        assert!(decoded.1);
+        assert_eq!(decoded.2, symbol_short!("shield"));
+
+        // Bit-packed fields keep only their low bits
+        let wide = PowerUp(0x1f, false, symbol_short!("shield"));
+        let decoded = PowerUp::deserialize(&env, &wide.serialize(&env)).unwrap();
+        assert_eq!(decoded.0, 0xf);
+
+        // Short and trailing input are rejected rather than panicking
+        assert_eq!(data.get(0), Some(0b0011_1000));
+        assert!(PowerUp::deserialize(&env, &data.slice(0..1)).is_none());
+        let mut long = data.clone();
+        long.push_back(0);
+        assert!(PowerUp::deserialize(&env, &long).is_none());
+        assert!(Position::deserialize(&env, &Bytes::from_array(&env, &[1, 2, 3])).is_none());
     }
 
     #[test]
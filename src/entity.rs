@@ -1,5 +1,6 @@
-use core::fmt;
-use soroban_sdk::{Env, FromVal, IntoVal, Symbol, TryFromVal, Val, Vec};
+use crate::component::{ComponentId, ComponentMask};
+use core::ops::Deref;
+use soroban_sdk::{Env, IntoVal, Map, TryFromVal, Val, Vec};
 
 /// A unique identifier for an entity in the ECS world
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -50,16 +51,18 @@ impl TryFromVal<Env, Val> for EntityId {
 #[derive(Debug, Clone)]
 pub struct Entity {
     id: EntityId,
-    component_types: Vec<Symbol>,
+    components: Vec<ComponentId>,
+    /// Bitset of `components`
+    signature: ComponentMask,
 }
 
 impl Entity {
     /// Create a new entity
-    pub fn new(id: EntityId) -> Self {
-        let env = soroban_sdk::Env::default();
+    pub fn new(env: &Env, id: EntityId) -> Self {
         Self {
             id,
-            component_types: Vec::new(&env),
+            components: Vec::new(env),
+            signature: ComponentMask::new(),
         }
     }
 
@@ -68,55 +71,49 @@ impl Entity {
         self.id
     }
 
-    /// Add a component type to this entity
-    pub fn add_component_type(&mut self, component_type: Symbol) {
-        self.component_types.push_back(component_type);
+    /// Add a component to this entity
+    pub fn add_component(&mut self, component_id: ComponentId) {
+        if !self.has_component(component_id) {
+            self.components.push_back(component_id);
+            self.signature = self.signature.with(component_id.bit());
+        }
     }
 
-    /// Remove a component type from this entity
-    pub fn remove_component_type(&mut self, component_type: &Symbol) -> bool {
-        let mut found = false;
-        let mut new_components = Vec::new(&soroban_sdk::Env::default());
-
-        for i in 0..self.component_types.len() {
-            let ctype = self.component_types.get(i).unwrap();
-            if ctype == *component_type {
-                found = true;
-            } else {
-                new_components.push_back(ctype.clone());
+    /// Remove a component from this entity
+    pub fn remove_component(&mut self, component_id: ComponentId) -> bool {
+        match self.components.first_index_of(component_id) {
+            Some(index) => {
+                self.components.remove(index);
+                self.signature = self.signature.without(component_id.bit());
+                true
             }
+            None => false,
         }
+    }
 
-        if found {
-            self.component_types = new_components;
-        }
-        found
+    /// Check if this entity has a specific component
+    pub fn has_component(&self, component_id: ComponentId) -> bool {
+        self.signature.contains(component_id.bit())
     }
 
-    /// Check if this entity has a specific component type
-    pub fn has_component(&self, component_type: &Symbol) -> bool {
-        for i in 0..self.component_types.len() {
-            let ctype = self.component_types.get(i).unwrap();
-            if ctype == *component_type {
-                return true;
-            }
-        }
-        false
+    /// Get all components of this entity, in insertion order
+    pub fn component_ids(&self) -> &Vec<ComponentId> {
+        &self.components
     }
 
-    /// Get all component types for this entity
-    pub fn component_types(&self) -> &Vec<Symbol> {
-        &self.component_types
+    /// Get the component bitset of this entity
+    pub fn signature(&self) -> ComponentMask {
+        self.signature
     }
 
     /// Get the number of components
     pub fn component_count(&self) -> usize {
-        self.component_types.len().try_into().unwrap()
+        self.components.len().try_into().unwrap()
     }
 
     /// Check if the entity has no components
     pub fn is_empty(&self) -> bool {
-        self.component_types.is_empty()
+        self.components.is_empty()
     }
 }
 
@@ -125,8 +122,8 @@ impl IntoVal<Env, Val> for Entity {
     fn into_val(&self, env: &Env) -> Val {
         // Serialize as a simple structure that Soroban can handle
         let id_val: Val = self.id.into_val(env);
-        let types_val: Val = self.component_types.clone().into_val(env);
-        (id_val, types_val).into_val(env)
+        let components_val: Val = self.components.clone().into_val(env);
+        (id_val, components_val).into_val(env)
     }
 }
 
@@ -134,82 +131,122 @@ impl TryFromVal<Env, Val> for Entity {
     type Error = soroban_sdk::ConversionError;
 
     fn try_from_val(env: &Env, val: &Val) -> Result<Self, Self::Error> {
-        let (id_val, types_val): (Val, Val) = TryFromVal::try_from_val(env, val)?;
+        let (id_val, components_val): (Val, Val) = TryFromVal::try_from_val(env, val)?;
         let id: EntityId = TryFromVal::try_from_val(env, &id_val)?;
-        let component_types: Vec<Symbol> = TryFromVal::try_from_val(env, &types_val)?;
+        let components: Vec<ComponentId> = TryFromVal::try_from_val(env, &components_val)?;
+        // The signature is derived from the component list rather than stored
+        let mut signature = ComponentMask::new();
+        for component_id in components.iter() {
+            signature = signature.with(component_id.bit());
+        }
         Ok(Entity {
             id,
-            component_types,
+            components,
+            signature,
         })
     }
 }
 
 /// Manager for handling entity lifecycle
+///
+/// Every slot carries a generation counter that is bumped on despawn, so a
+/// handle to a despawned entity never matches whichever entity reuses its ID.
 #[derive(Debug, Clone)]
 pub struct EntityManager {
     next_id: u64,
     entities: Vec<Entity>,
     free_list: Vec<u64>,
+    /// Current generation per slot, indexed by `id - 1`
+    generations: Vec<u32>,
+    /// Position of each live entity in `entities`, by ID
+    positions: Map<u64, u32>,
 }
 
 impl EntityManager {
     /// Create a new entity manager
-    pub fn new() -> Self {
-        let env = soroban_sdk::Env::default();
+    pub fn new(env: &Env) -> Self {
         Self {
             next_id: 1,
-            entities: Vec::new(&env),
-            free_list: Vec::new(&env),
+            entities: Vec::new(env),
+            free_list: Vec::new(env),
+            generations: Vec::new(env),
+            positions: Map::new(env),
         }
     }
 
     /// Spawn a new entity
     pub fn spawn(&mut self) -> EntityId {
-        let id = if self.free_list.len() > 0 {
-            let freed_id = self.free_list.get(self.free_list.len() - 1).unwrap();
-            self.free_list.remove(self.free_list.len() - 1);
-            freed_id
+        let entity_id = if let Some(freed_id) = self.free_list.pop_back() {
+            EntityId::new(freed_id, self.generation(freed_id))
         } else {
             let id = self.next_id;
             self.next_id += 1;
-            id
+            self.generations.push_back(0);
+            EntityId::new(id, 0)
         };
 
-        let entity_id = EntityId::new(id, 0);
-        let entity = Entity::new(entity_id);
+        let entity = Entity::new(self.entities.env(), entity_id);
+        self.positions.set(entity_id.id(), self.entities.len());
         self.entities.push_back(entity);
         entity_id
     }
 
     /// Despawn an entity
+    ///
+    /// The last entity is swapped into the freed position, so iteration order
+    /// is not preserved across despawns.
     pub fn despawn(&mut self, entity_id: EntityId) -> bool {
-        for i in 0..self.entities.len() {
-            let entity = self.entities.get(i).unwrap();
-            if entity.id() == entity_id {
-                self.entities.remove(i);
-                self.free_list.push_back(entity_id.id());
-                return true;
-            }
+        let index = match self.position(entity_id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let last = self.entities.pop_back().unwrap();
+        if index < self.entities.len() {
+            self.positions.set(last.id().id(), index);
+            self.entities.set(index, last);
         }
-        false
+        self.positions.remove(entity_id.id());
+
+        let slot = (entity_id.id() - 1) as u32;
+        self.generations
+            .set(slot, entity_id.generation().wrapping_add(1));
+        self.free_list.push_back(entity_id.id());
+        true
     }
 
-    /// Get an entity by ID
-    pub fn get_entity(&self, entity_id: EntityId) -> Option<Entity> {
-        for i in 0..self.entities.len() {
-            let entity = self.entities.get(i).unwrap();
-            if entity.id() == entity_id {
-                return Some(entity.clone());
-            }
+    /// Get the current generation of an entity slot
+    ///
+    /// Returns 0 for slots that have never been allocated.
+    pub fn generation(&self, id: u64) -> u32 {
+        if id == 0 {
+            return 0;
         }
-        None
+        self.generations.get((id - 1) as u32).unwrap_or(0)
     }
 
-    /// Get a mutable reference to an entity by ID
-    pub fn get_entity_mut(&mut self, entity_id: EntityId) -> Option<Entity> {
-        // Since soroban_sdk::Vec doesn't have get_mut, we'll need to restructure this
-        // For now, return a clone - this is a limitation of the Soroban SDK
-        self.get_entity(entity_id)
+    /// Check whether a handle refers to the current occupant of its slot
+    pub fn is_stale(&self, entity_id: EntityId) -> bool {
+        !self.exists(entity_id)
+    }
+
+    /// Get an entity by ID
+    pub fn get_entity(&self, entity_id: EntityId) -> Option<Entity> {
+        let index = self.position(entity_id)?;
+        self.entities.get(index)
+    }
+
+    /// Get a mutable handle to an entity by ID
+    ///
+    /// Changes made through the returned guard are written back to the
+    /// manager when it is dropped.
+    pub fn get_entity_mut(&mut self, entity_id: EntityId) -> Option<EntityMut<'_>> {
+        let index = self.position(entity_id)?;
+        let entity = self.entities.get(index)?;
+        Some(EntityMut {
+            entities: &mut self.entities,
+            index,
+            entity,
+        })
     }
 
     /// Get the total number of entities
@@ -219,17 +256,19 @@ impl EntityManager {
 
     /// Check if an entity exists
     pub fn exists(&self, entity_id: EntityId) -> bool {
-        for i in 0..self.entities.len() {
-            let entity = self.entities.get(i).unwrap();
-            if entity.id() == entity_id {
-                return true;
-            }
+        self.position(entity_id).is_some()
+    }
+
+    /// Position of a live entity in `entities`
+    fn position(&self, entity_id: EntityId) -> Option<u32> {
+        if self.generation(entity_id.id()) != entity_id.generation() {
+            return None;
         }
-        false
+        self.positions.get(entity_id.id())
     }
 
     /// Iterate over all entities
-    pub fn iter_entities(&self) -> EntityIterator {
+    pub fn iter_entities(&self) -> EntityIterator<'_> {
         EntityIterator {
             entities: &self.entities,
             index: 0,
@@ -237,7 +276,7 @@ impl EntityManager {
     }
 
     /// Iterate over all entities mutably
-    pub fn iter_entities_mut(&mut self) -> EntityIteratorMut {
+    pub fn iter_entities_mut(&mut self) -> EntityIteratorMut<'_> {
         EntityIteratorMut {
             entities: &mut self.entities,
             index: 0,
@@ -245,16 +284,16 @@ impl EntityManager {
     }
 }
 
-impl Default for EntityManager {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 // Soroban SDK trait implementations for EntityManager
 impl IntoVal<Env, Val> for EntityManager {
     fn into_val(&self, env: &Env) -> Val {
-        (self.next_id, self.entities.clone(), self.free_list.clone()).into_val(env)
+        (
+            self.next_id,
+            self.entities.clone(),
+            self.free_list.clone(),
+            self.generations.clone(),
+        )
+            .into_val(env)
     }
 }
 
@@ -262,16 +301,64 @@ impl TryFromVal<Env, Val> for EntityManager {
     type Error = soroban_sdk::ConversionError;
 
     fn try_from_val(env: &Env, val: &Val) -> Result<Self, Self::Error> {
-        let (next_id, entities, free_list): (u64, Vec<Entity>, Vec<u64>) =
+        let (next_id, entities, free_list, generations): (u64, Vec<Entity>, Vec<u64>, Vec<u32>) =
             TryFromVal::try_from_val(env, val)?;
+        // Positions are derived from the entity list rather than stored
+        let mut positions = Map::new(env);
+        for (index, entity) in entities.iter().enumerate() {
+            positions.set(entity.id().id(), index as u32);
+        }
         Ok(EntityManager {
             next_id,
             entities,
             free_list,
+            generations,
+            positions,
         })
     }
 }
 
+/// Mutable handle to an entity stored in an [`EntityManager`]
+///
+/// `soroban_sdk::Vec` only hands out owned values, so the guard keeps a copy
+/// of the entity and writes it back into its slot on drop.
+///
+/// An entity's fields are its component list and signature, which must stay
+/// in step with the world's archetypes, storage and change versions. Outside
+/// the crate the guard is read-only; add and remove components through
+/// [`World`](crate::world::World) instead.
+pub struct EntityMut<'a> {
+    entities: &'a mut Vec<Entity>,
+    index: u32,
+    entity: Entity,
+}
+
+impl Deref for EntityMut<'_> {
+    type Target = Entity;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entity
+    }
+}
+
+impl EntityMut<'_> {
+    /// Add a component to the entity
+    pub(crate) fn add_component(&mut self, component_id: ComponentId) {
+        self.entity.add_component(component_id);
+    }
+
+    /// Remove a component from the entity
+    pub(crate) fn remove_component(&mut self, component_id: ComponentId) -> bool {
+        self.entity.remove_component(component_id)
+    }
+}
+
+impl Drop for EntityMut<'_> {
+    fn drop(&mut self) {
+        self.entities.set(self.index, self.entity.clone());
+    }
+}
+
 /// Iterator over entities
 pub struct EntityIterator<'a> {
     entities: &'a Vec<Entity>,
@@ -329,14 +416,15 @@ mod tests {
     fn test_entity_creation() {
         let env = Env::default();
         let entity_id = EntityId::new(1, 0);
-        let entity = Entity::new(entity_id);
+        let entity = Entity::new(&env, entity_id);
         assert_eq!(entity.id(), entity_id);
         assert!(entity.is_empty());
     }
 
     #[test]
     fn test_entity_manager() {
-        let mut manager = EntityManager::new();
+        let env = Env::default();
+        let mut manager = EntityManager::new(&env);
         assert_eq!(manager.entity_count(), 0);
 
         let entity_id = manager.spawn();
@@ -347,4 +435,44 @@ mod tests {
         assert_eq!(manager.entity_count(), 0);
         assert!(!manager.exists(entity_id));
     }
+
+    #[test]
+    fn test_entity_generations() {
+        let env = Env::default();
+        let mut manager = EntityManager::new(&env);
+
+        let first = manager.spawn();
+        assert_eq!(first.generation(), 0);
+        assert!(manager.despawn(first));
+
+        let second = manager.spawn();
+        assert_eq!(second.id(), first.id());
+        assert_eq!(second.generation(), 1);
+        assert!(manager.exists(second));
+        assert!(!manager.exists(first));
+        assert!(manager.is_stale(first));
+        assert!(!manager.is_stale(second));
+
+        // A stale handle cannot despawn the slot's new occupant
+        assert!(!manager.despawn(first));
+        assert!(manager.exists(second));
+    }
+
+    #[test]
+    fn test_entity_mut_writes_back() {
+        let env = Env::default();
+        let mut manager = EntityManager::new(&env);
+        let entity_id = manager.spawn();
+
+        manager
+            .get_entity_mut(entity_id)
+            .unwrap()
+            .add_component(ComponentId::new(3));
+
+        let entity = manager.get_entity(entity_id).unwrap();
+        assert!(entity.has_component(ComponentId::new(3)));
+        assert!(!entity.has_component(ComponentId::new(1)));
+        assert_eq!(entity.signature().bits(), 0b100);
+        assert_eq!(entity.component_count(), 1);
+    }
 }
@@ -1,28 +1,47 @@
-use alloc::string::String;
-use soroban_sdk::{contracttype, symbol_short, Bytes, Env, IntoVal, Symbol, TryFromVal, Val, Vec};
+use crate::storage::TtlPolicy;
+use crate::system::SystemParam;
+use crate::world::World;
+use alloc::vec::Vec as StdVec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::RangeBounds;
+use core::sync::atomic::{AtomicU32, Ordering};
+use soroban_sdk::{contracttype, symbol_short, Bytes, Env, IntoVal, Map, Symbol, Val};
 
+/// An encoded event
+///
+/// Events sent through a `World` are stamped with the ledger timestamp and
+/// sequence and the world's tick, which orders them across transactions.
 #[contracttype]
 #[derive(Debug, Clone)]
 pub struct Event {
     pub event_type: Symbol,
     pub data: Bytes,
     pub timestamp: u64,
+    /// Ledger sequence the event was sent in
+    pub ledger: u32,
+    /// World tick the event was sent in
+    pub tick: u64,
 }
 impl Event {
     pub fn new(event_type: Symbol, data: Bytes) -> Self {
-        Self {
-            event_type,
-            data,
-            timestamp: 0,
-        }
+        Self::with_timestamp(event_type, data, 0)
     }
     pub fn with_timestamp(event_type: Symbol, data: Bytes, timestamp: u64) -> Self {
         Self {
             event_type,
             data,
             timestamp,
+            ledger: 0,
+            tick: 0,
         }
     }
+    /// Record the current ledger and `tick` as the time this event was sent
+    pub fn stamp(&mut self, env: &Env, tick: u64) {
+        self.timestamp = env.ledger().timestamp();
+        self.ledger = env.ledger().sequence();
+        self.tick = tick;
+    }
     pub fn event_type(&self) -> &Symbol {
         &self.event_type
     }
@@ -32,59 +51,449 @@ impl Event {
     pub fn timestamp(&self) -> u64 {
         self.timestamp
     }
+    pub fn ledger(&self) -> u32 {
+        self.ledger
+    }
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+    /// Check if this event was sent during one of the ticks in `range`
+    pub fn in_ticks(&self, range: &impl RangeBounds<u64>) -> bool {
+        range.contains(&self.tick)
+    }
+    /// Check if this event was sent in one of the ledgers in `range`
+    pub fn in_ledgers(&self, range: &impl RangeBounds<u32>) -> bool {
+        range.contains(&self.ledger)
+    }
 }
 
-pub struct EventReader<'a> {
-    events: &'a [Event],
-    event_type: Symbol,
-    read_index: usize,
+/// Events sent during the current and the previous tick
+///
+/// Double buffered: [`Events::update`] runs once per tick, dropping the
+/// older buffer and starting a new one, so every event stays readable for
+/// one full tick after it is sent. Readers track their own position with an
+/// [`EventCursor`], so several systems can read the same events independently.
+///
+/// Every queue gets its own ID, which its cursors are bound to; a clone keeps
+/// the ID of the queue it was cloned from.
+#[derive(Debug, Clone)]
+pub struct Events<T = Event> {
+    /// ID of this queue, unique within the program
+    id: u32,
+    /// Events of the previous tick
+    events_a: EventSequence<T>,
+    /// Events of the current tick
+    events_b: EventSequence<T>,
+    /// Total number of events ever sent; the next event's ID
+    event_count: usize,
 }
-impl<'a> EventReader<'a> {
-    pub fn new(events: &'a [Event], event_type: Symbol) -> Self {
+
+#[derive(Debug, Clone)]
+struct EventSequence<T> {
+    events: StdVec<T>,
+    /// ID of the first event in this buffer
+    start_event_count: usize,
+}
+
+impl<T> EventSequence<T> {
+    fn new(start_event_count: usize) -> Self {
         Self {
-            events,
-            event_type,
-            read_index: 0,
+            events: StdVec::new(),
+            start_event_count,
         }
     }
-    pub fn read(&mut self) -> Option<&Event> {
-        while self.read_index < self.events.len() {
-            let event = &self.events[self.read_index];
-            self.read_index += 1;
-            if event.event_type() == &self.event_type {
-                return Some(event);
-            }
+}
+
+/// ID of the next [`Events`] queue created
+static NEXT_EVENTS_ID: AtomicU32 = AtomicU32::new(1);
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_EVENTS_ID.fetch_add(1, Ordering::Relaxed),
+            events_a: EventSequence::new(0),
+            events_b: EventSequence::new(0),
+            event_count: 0,
         }
-        None
     }
-    pub fn has_more(&self) -> bool {
-        self.read_index < self.events.len()
+
+    /// Send an event, returning its ID
+    pub fn send(&mut self, event: T) -> usize {
+        let id = self.event_count;
+        self.events_b.events.push(event);
+        self.event_count += 1;
+        id
+    }
+
+    /// Rotate the buffers, dropping events sent before the previous update
+    pub fn update(&mut self) {
+        core::mem::swap(&mut self.events_a, &mut self.events_b);
+        self.events_b = EventSequence::new(self.event_count);
+    }
+
+    /// Get a cursor that will read every event still buffered
+    pub fn get_cursor(&self) -> EventCursor<T> {
+        EventCursor {
+            queue: Some(self.id),
+            last_event_count: 0,
+            marker: PhantomData,
+        }
+    }
+
+    /// Get a cursor that will only read events sent from now on
+    pub fn get_cursor_current(&self) -> EventCursor<T> {
+        EventCursor {
+            queue: Some(self.id),
+            last_event_count: self.event_count,
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate over every buffered event, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.events_a
+            .events
+            .iter()
+            .chain(self.events_b.events.iter())
+    }
+
+    /// Iterate over the events sent since the last update
+    pub fn iter_current_update_events(&self) -> impl Iterator<Item = &T> + '_ {
+        self.events_b.events.iter()
+    }
+
+    /// ID of the oldest event still buffered
+    pub fn oldest_event_count(&self) -> usize {
+        self.events_a.start_event_count
+    }
+
+    /// Total number of events ever sent
+    pub fn event_count(&self) -> usize {
+        self.event_count
+    }
+
+    /// Drop every buffered event; cursors skip past them
+    pub fn clear(&mut self) {
+        self.events_a = EventSequence::new(self.event_count);
+        self.events_b = EventSequence::new(self.event_count);
+    }
+
+    /// Get the number of buffered events
+    pub fn len(&self) -> usize {
+        self.events_a.events.len() + self.events_b.events.len()
+    }
+
+    /// Check if no events are buffered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
-    pub fn reset(&mut self) {
-        self.read_index = 0;
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-pub struct EventWriter<'a> {
-    events: &'a mut Vec<Event>,
+/// A reader's position in an [`Events`] collection
+///
+/// Each cursor sees every event exactly once, as long as it reads at least
+/// once per tick. Events that rotate out before it reads are missed.
+///
+/// A cursor is bound to one queue: the one it was created from, or the first
+/// one it reads. Reading a different queue rebinds it and starts it over at
+/// that queue's oldest buffered event, so a cursor moved back and forth
+/// between queues sees their events again. Keep one cursor per queue.
+pub struct EventCursor<T = Event> {
+    /// ID of the queue this cursor reads, once bound
+    queue: Option<u32>,
+    last_event_count: usize,
+    marker: PhantomData<fn() -> T>,
 }
-impl<'a> EventWriter<'a> {
-    pub fn new(events: &'a mut Vec<Event>) -> Self {
-        Self { events }
+
+impl<T> EventCursor<T> {
+    /// Read every event this cursor has not seen yet
+    pub fn read<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> + 'a
+    where
+        T: 'a,
+    {
+        self.advance(events)
     }
-    pub fn send(&mut self, event: Event) {
-        self.events.push_back(event);
+
+    /// Get the number of unread events
+    pub fn len(&self, events: &Events<T>) -> usize {
+        self.unread(events)
     }
-    pub fn send_with_data(&mut self, event_type: Symbol, data: Bytes) {
-        let event = Event::new(event_type, data);
-        self.send(event);
+
+    /// Check if there are no unread events
+    pub fn is_empty(&self, events: &Events<T>) -> bool {
+        self.len(events) == 0
+    }
+
+    /// Get the number of events that rotated out before this cursor read them
+    pub fn missed_events(&self, events: &Events<T>) -> usize {
+        events
+            .oldest_event_count()
+            .saturating_sub(self.position(events))
     }
-    pub fn send_batch(&mut self, events: Vec<Event>) {
+
+    /// Mark every buffered event as read
+    pub fn clear(&mut self, events: &Events<T>) {
+        self.skip_all(events);
+    }
+
+    /// Check if this cursor is bound to `events`
+    ///
+    /// An unbound cursor binds to the first queue it reads.
+    pub fn is_bound_to<U>(&self, events: &Events<U>) -> bool {
+        self.queue == Some(events.id)
+    }
+
+    /// ID of the last event this cursor read in `events`, counting from 0 if
+    /// it is bound to another queue
+    fn position<U>(&self, events: &Events<U>) -> usize {
+        match self.queue {
+            Some(queue) if queue != events.id => 0,
+            _ => self.last_event_count,
+        }
+    }
+
+    /// Read a queue holding the encoded form of `T`, such as a world's
+    /// [`Event`] queues read by a typed [`EventReader`]
+    fn advance<'a, U: 'a>(&mut self, events: &'a Events<U>) -> impl Iterator<Item = &'a U> + 'a {
+        let position = self.position(events);
+        let a_skip = position.saturating_sub(events.events_a.start_event_count);
+        let b_skip = position.saturating_sub(events.events_b.start_event_count);
+        self.queue = Some(events.id);
+        self.last_event_count = events.event_count;
+        events.events_a.events[a_skip.min(events.events_a.events.len())..]
+            .iter()
+            .chain(events.events_b.events[b_skip.min(events.events_b.events.len())..].iter())
+    }
+
+    /// The same position as a cursor over another item type
+    fn cast<U>(&self) -> EventCursor<U> {
+        EventCursor {
+            queue: self.queue,
+            last_event_count: self.last_event_count,
+            marker: PhantomData,
+        }
+    }
+
+    fn skip_all<U>(&mut self, events: &Events<U>) {
+        self.queue = Some(events.id);
+        self.last_event_count = events.event_count;
+    }
+
+    fn unread<U>(&self, events: &Events<U>) -> usize {
+        let seen = self.position(events).max(events.oldest_event_count());
+        events.event_count.saturating_sub(seen)
+    }
+}
+
+impl<T> Default for EventCursor<T> {
+    fn default() -> Self {
+        Self {
+            queue: None,
+            last_event_count: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for EventCursor<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue,
+            last_event_count: self.last_event_count,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for EventCursor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventCursor")
+            .field("queue", &self.queue)
+            .field("last_event_count", &self.last_event_count)
+            .finish()
+    }
+}
+
+/// Typed reader of one event type in a [`World`]
+///
+/// Keeps its own [`EventCursor`], so a system that stores its reader sees
+/// each event once no matter how many other readers there are. Events that
+/// fail to decode are skipped.
+///
+/// As a [`SystemParam`] fetched while [`World::run_system`] runs a system,
+/// the reader uses a cursor the world keeps under that system's name, so the
+/// system sees each event once across runs. Fetched outside `run_system` it
+/// starts at the oldest buffered event, like [`EventReader::new`].
+pub struct EventReader<E: EventTrait> {
+    cursor: EventCursor<E>,
+    /// System whose cursor in the world this reader uses
+    system: Option<Symbol>,
+}
+impl<E: EventTrait> EventReader<E> {
+    /// Create a reader that starts at the oldest buffered event
+    pub fn new() -> Self {
+        Self {
+            cursor: EventCursor::default(),
+            system: None,
+        }
+    }
+    /// Read every event of type `E` this reader has not seen yet
+    pub fn read<'w>(&mut self, world: &'w World) -> impl Iterator<Item = E> + 'w {
+        self.read_where(world, |_| true)
+    }
+    /// Read the unseen events sent during the ticks in `range`
+    ///
+    /// Unseen events outside the range are skipped, not kept for later.
+    pub fn read_ticks<'w, R>(&mut self, world: &'w World, range: R) -> impl Iterator<Item = E> + 'w
+    where
+        R: RangeBounds<u64> + 'w,
+    {
+        self.read_where(world, move |event| event.in_ticks(&range))
+    }
+    /// Read the unseen events sent in the ledgers in `range`
+    ///
+    /// Unseen events outside the range are skipped, not kept for later.
+    pub fn read_ledgers<'w, R>(
+        &mut self,
+        world: &'w World,
+        range: R,
+    ) -> impl Iterator<Item = E> + 'w
+    where
+        R: RangeBounds<u32> + 'w,
+    {
+        self.read_where(world, move |event| event.in_ledgers(&range))
+    }
+    fn read_where<'w>(
+        &mut self,
+        world: &'w World,
+        filter: impl Fn(&Event) -> bool + 'w,
+    ) -> impl Iterator<Item = E> + 'w {
+        let env = world.env();
+        let event_type = E::event_type();
+        self.load(world, &event_type);
+        let events = world
+            .events(&event_type)
+            .map(|events| self.cursor.advance(events));
+        self.store(world, &event_type);
+        events
+            .into_iter()
+            .flatten()
+            .filter(move |event| filter(event))
+            .filter_map(move |event| E::deserialize(env, event.data()))
+    }
+    /// Get the number of unread events
+    pub fn len(&self, world: &World) -> usize {
+        let event_type = E::event_type();
+        let cursor = match &self.system {
+            Some(system) => world.system_cursor(system, &event_type).cast(),
+            None => self.cursor.clone(),
+        };
+        match world.events(&event_type) {
+            Some(events) => cursor.unread(events),
+            None => 0,
+        }
+    }
+    pub fn is_empty(&self, world: &World) -> bool {
+        self.len(world) == 0
+    }
+    /// Skip every unread event
+    pub fn clear(&mut self, world: &World) {
+        let event_type = E::event_type();
+        if let Some(events) = world.events(&event_type) {
+            self.cursor.skip_all(events);
+            self.store(world, &event_type);
+        }
+    }
+    /// Pick up the system's cursor, which other readers may have moved
+    fn load(&mut self, world: &World, event_type: &Symbol) {
+        if let Some(system) = &self.system {
+            self.cursor = world.system_cursor(system, event_type).cast();
+        }
+    }
+    fn store(&self, world: &World, event_type: &Symbol) {
+        if let Some(system) = &self.system {
+            world.set_system_cursor(system, event_type, self.cursor.cast());
+        }
+    }
+}
+impl<E: EventTrait> Default for EventReader<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+/// Typed sender of one event type into a [`World`]
+///
+/// A writer created with [`EventWriter::publishing`] also publishes every
+/// event as a contract event, even if `E` does not opt in itself.
+pub struct EventWriter<E: EventTrait> {
+    publish: bool,
+    marker: PhantomData<fn(E)>,
+}
+impl<E: EventTrait> EventWriter<E> {
+    pub fn new() -> Self {
+        Self {
+            publish: false,
+            marker: PhantomData,
+        }
+    }
+    /// Create a writer that also publishes each event as a contract event
+    pub fn publishing() -> Self {
+        Self {
+            publish: true,
+            marker: PhantomData,
+        }
+    }
+    /// Encode and send an event, returning its ID
+    pub fn send(&self, world: &mut World, event: E) -> usize {
+        if self.publish && !E::published() {
+            publish(world.env(), &event);
+        }
+        world.send(event)
+    }
+    pub fn send_batch(&self, world: &mut World, events: impl IntoIterator<Item = E>) {
         for event in events {
-            self.send(event);
+            self.send(world, event);
         }
     }
 }
+impl<E: EventTrait> Default for EventWriter<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<E: EventTrait> SystemParam for EventReader<E> {
+    type Param = Self;
+    type Fetch = Self;
+
+    fn fetch(world: &World) -> Self::Fetch {
+        Self {
+            cursor: EventCursor::default(),
+            system: world.running_system().cloned(),
+        }
+    }
+
+    fn fetch_mut(world: &mut World) -> Self::Fetch {
+        Self::fetch(world)
+    }
+}
+impl<E: EventTrait> SystemParam for EventWriter<E> {
+    type Param = Self;
+    type Fetch = Self;
+
+    fn fetch(_world: &World) -> Self::Fetch {
+        Self::new()
+    }
+
+    fn fetch_mut(_world: &mut World) -> Self::Fetch {
+        Self::new()
+    }
+}
 
 pub trait EventTrait {
     fn event_type() -> Symbol;
@@ -92,10 +501,318 @@ pub trait EventTrait {
     fn deserialize(env: &Env, data: &Bytes) -> Option<Self>
     where
         Self: Sized;
+
+    /// Whether sending this event also publishes it as a contract event
+    fn published() -> bool {
+        false
+    }
+
+    /// The entity this event concerns, used as its last contract event topic
+    fn entity(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// First topic of every contract event published by Cougr
+pub const EVENT_TOPIC: Symbol = symbol_short!("cougr");
+
+/// Publish an event as a contract event visible to clients and indexers
+///
+/// Topics are `("cougr", event_type, entity)`, with a void entity for events
+/// that do not concern one; the data is the event's encoded bytes. Must be
+/// called while a contract is executing.
+pub fn publish<E: EventTrait>(env: &Env, event: &E) {
+    let topics = (EVENT_TOPIC, E::event_type(), event.entity());
+    env.events().publish_event(&ContractEvent {
+        topics: topics.into_val(env),
+        data: event.serialize(env).into_val(env),
+    });
+}
+
+/// A contract event with prebuilt topics and data
+struct ContractEvent {
+    topics: soroban_sdk::Vec<Val>,
+    data: Val,
+}
+
+impl soroban_sdk::events::Event for ContractEvent {
+    fn topics(&self, _env: &Env) -> soroban_sdk::Vec<Val> {
+        self.topics.clone()
+    }
+
+    fn data(&self, _env: &Env) -> Val {
+        self.data
+    }
+}
+
+/// When a queued event stops being delivered
+#[contracttype]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// Delivered until it is read by every consumer or evicted
+    Never,
+    /// Expires this many ticks after the tick it was sent in
+    Ticks(u64),
+    /// Expires this many ledgers after the ledger it was sent in
+    Ledgers(u32),
+}
+
+/// What a full [`EventQueue`] does with a new event
+#[contracttype]
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Evict the oldest queued event to make room
+    #[default]
+    DropOldest = 0,
+    /// Discard the new event
+    DropNewest = 1,
+    /// Panic, aborting the transaction
+    Panic = 2,
+}
+
+/// An event waiting in an [`EventQueue`]
+#[contracttype]
+#[derive(Debug, Clone)]
+pub struct QueuedEvent {
+    pub id: u64,
+    pub event: Event,
+    pub expiry: Expiry,
+}
+
+impl QueuedEvent {
+    /// Check if this event has expired at `tick` and the current ledger
+    pub fn is_expired(&self, env: &Env, tick: u64) -> bool {
+        match self.expiry {
+            Expiry::Never => false,
+            Expiry::Ticks(ticks) => tick >= self.event.tick().saturating_add(ticks),
+            Expiry::Ledgers(ledgers) => {
+                env.ledger().sequence() >= self.event.ledger().saturating_add(ledgers)
+            }
+        }
+    }
+}
+
+/// Ledger key of an [`EventQueue`]
+#[contracttype]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventQueueKey {
+    Queue(Symbol),
+}
+
+/// Persisted contents of an [`EventQueue`]
+#[contracttype]
+#[derive(Debug, Clone)]
+pub struct EventQueueState {
+    /// ID of the next event pushed
+    pub next_id: u64,
+    pub entries: soroban_sdk::Vec<QueuedEvent>,
+    /// ID of the next event each consumer will read
+    pub cursors: Map<Symbol, u64>,
+}
+
+/// Event queue kept in contract storage between invocations
+///
+/// Lets events sent in one transaction be processed in a later one. Each
+/// consumer has a persisted cursor, so it reads every event once. Events
+/// leave the queue when they expire or when the queue is full and the
+/// [`OverflowPolicy`] evicts them, so consumers that start reading later
+/// still see everything queued.
+///
+/// The world's tick starts over on every invocation, so callers pass the
+/// game's own tick wherever tick-based expiry matters. Changes are written
+/// by [`EventQueue::flush`].
+pub struct EventQueue {
+    env: Env,
+    key: EventQueueKey,
+    capacity: u32,
+    overflow: OverflowPolicy,
+    expiry: Expiry,
+    ttl_policy: Option<TtlPolicy>,
+    state: EventQueueState,
+    dirty: bool,
+}
+
+impl EventQueue {
+    /// Default number of events a queue holds
+    pub const DEFAULT_CAPACITY: u32 = 64;
+
+    /// Open the queue stored under `name` in persistent storage
+    pub fn open(env: &Env, name: Symbol) -> Self {
+        let key = EventQueueKey::Queue(name);
+        let state = env
+            .storage()
+            .persistent()
+            .get(&key)
+            .unwrap_or_else(|| EventQueueState {
+                next_id: 0,
+                entries: soroban_sdk::Vec::new(env),
+                cursors: Map::new(env),
+            });
+        Self {
+            env: env.clone(),
+            key,
+            capacity: Self::DEFAULT_CAPACITY,
+            overflow: OverflowPolicy::default(),
+            expiry: Expiry::Never,
+            ttl_policy: None,
+            state,
+            dirty: false,
+        }
+    }
+
+    /// Set the maximum number of queued events
+    ///
+    /// A capacity of 0 is raised to 1, so the queue can always hold the
+    /// newest event.
+    pub fn with_capacity(mut self, capacity: u32) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Set what happens when an event is pushed onto a full queue
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Set the expiry of events pushed without one
+    pub fn with_expiry(mut self, expiry: Expiry) -> Self {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Extend the queue's ledger entry according to `policy` on flush
+    pub fn with_ttl_policy(mut self, policy: TtlPolicy) -> Self {
+        self.ttl_policy = Some(policy);
+        self
+    }
+
+    /// Stamp and push an event with the queue's default expiry
+    ///
+    /// Returns `false` if the queue is full and the event was dropped.
+    pub fn push(&mut self, event: Event, tick: u64) -> bool {
+        self.push_with_expiry(event, tick, self.expiry)
+    }
+
+    /// Stamp and push an event with its own expiry
+    ///
+    /// Returns `false` if the queue is full and the event was dropped.
+    pub fn push_with_expiry(&mut self, mut event: Event, tick: u64, expiry: Expiry) -> bool {
+        self.prune(tick);
+        if self.state.entries.len() >= self.capacity {
+            match self.overflow {
+                // A queue reopened with a lower capacity may be over it
+                OverflowPolicy::DropOldest => {
+                    while self.state.entries.len() >= self.capacity {
+                        self.state.entries.pop_front();
+                    }
+                }
+                OverflowPolicy::DropNewest => return false,
+                OverflowPolicy::Panic => panic!("event queue is full"),
+            }
+        }
+        event.stamp(&self.env, tick);
+        let id = self.state.next_id;
+        self.state.next_id += 1;
+        self.state
+            .entries
+            .push_back(QueuedEvent { id, event, expiry });
+        self.dirty = true;
+        true
+    }
+
+    /// Encode, stamp and push a typed event
+    pub fn send<E: EventTrait>(&mut self, event: &E, tick: u64) -> bool {
+        let data = event.serialize(&self.env);
+        self.push(Event::new(E::event_type(), data), tick)
+    }
+
+    /// Read every unexpired event `consumer` has not read yet
+    pub fn read_events(&mut self, consumer: &Symbol, tick: u64) -> soroban_sdk::Vec<Event> {
+        self.prune(tick);
+        let cursor = self.state.cursors.get(consumer.clone()).unwrap_or(0);
+        let mut events = soroban_sdk::Vec::new(&self.env);
+        for entry in self.state.entries.iter() {
+            if entry.id >= cursor {
+                events.push_back(entry.event);
+            }
+        }
+        if cursor != self.state.next_id {
+            self.state.cursors.set(consumer.clone(), self.state.next_id);
+            self.dirty = true;
+        }
+        events
+    }
+
+    /// Read every unexpired event of type `E` that `consumer` has not read yet
+    ///
+    /// The consumer's cursor moves past events of other types as well.
+    pub fn read<E: EventTrait>(&mut self, consumer: &Symbol, tick: u64) -> StdVec<E> {
+        self.read_events(consumer, tick)
+            .iter()
+            .filter(|event| event.event_type() == &E::event_type())
+            .filter_map(|event| E::deserialize(&self.env, event.data()))
+            .collect()
+    }
+
+    /// Get the number of events `consumer` has not read yet
+    pub fn unread(&self, consumer: &Symbol) -> u32 {
+        let cursor = self.state.cursors.get(consumer.clone()).unwrap_or(0);
+        self.state
+            .entries
+            .iter()
+            .filter(|entry| entry.id >= cursor)
+            .count() as u32
+    }
+
+    /// Drop expired events
+    pub fn prune(&mut self, tick: u64) {
+        let mut kept = soroban_sdk::Vec::new(&self.env);
+        for entry in self.state.entries.iter() {
+            if !entry.is_expired(&self.env, tick) {
+                kept.push_back(entry);
+            }
+        }
+        if kept.len() != self.state.entries.len() {
+            self.state.entries = kept;
+            self.dirty = true;
+        }
+    }
+
+    /// Get the number of queued events
+    pub fn len(&self) -> u32 {
+        self.state.entries.len()
+    }
+
+    /// Check if no events are queued
+    pub fn is_empty(&self) -> bool {
+        self.state.entries.is_empty()
+    }
+
+    /// Check if there are changes that have not been flushed
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Write the queue and cursors to contract storage if they changed
+    pub fn flush(&mut self) {
+        let storage = self.env.storage().persistent();
+        if self.dirty {
+            storage.set(&self.key, &self.state);
+            self.dirty = false;
+        }
+        if let Some(policy) = self.ttl_policy {
+            if storage.has(&self.key) {
+                storage.extend_ttl(&self.key, policy.threshold, policy.extend_to);
+            }
+        }
+    }
 }
 
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, cougr_derive::Event)]
+#[event(name = "collision")]
 pub struct CollisionEvent {
     pub entity_a: u64,
     pub entity_b: u64,
@@ -109,72 +826,11 @@ impl CollisionEvent {
             collision_type,
         }
     }
-    fn to_string(&self) -> String {
-        let s = "collision"; // Simplified for now
-        String::from(s)
-    }
-}
-impl EventTrait for CollisionEvent {
-    fn event_type() -> Symbol {
-        symbol_short!("collision")
-    }
-    fn serialize(&self, env: &Env) -> Bytes {
-        let mut bytes = Bytes::new(env);
-        bytes.append(&Bytes::from_slice(env, &self.entity_a.to_be_bytes()));
-        bytes.append(&Bytes::from_slice(env, &self.entity_b.to_be_bytes()));
-        // Serialize the collision_type symbol by converting to Val and then to u64
-        let symbol_val: Val = self.collision_type.to_val();
-        let symbol_bits = symbol_val.get_payload();
-        bytes.append(&Bytes::from_slice(env, &symbol_bits.to_be_bytes()));
-        bytes
-    }
-    fn deserialize(env: &Env, data: &Bytes) -> Option<Self> {
-        if data.len() < 24 {
-            return None;
-        }
-        let entity_a = u64::from_be_bytes([
-            data.get(0)?,
-            data.get(1)?,
-            data.get(2)?,
-            data.get(3)?,
-            data.get(4)?,
-            data.get(5)?,
-            data.get(6)?,
-            data.get(7)?,
-        ]);
-        let entity_b = u64::from_be_bytes([
-            data.get(8)?,
-            data.get(9)?,
-            data.get(10)?,
-            data.get(11)?,
-            data.get(12)?,
-            data.get(13)?,
-            data.get(14)?,
-            data.get(15)?,
-        ]);
-        // Deserialize the symbol from its Val representation
-        let symbol_bits = u64::from_be_bytes([
-            data.get(16)?,
-            data.get(17)?,
-            data.get(18)?,
-            data.get(19)?,
-            data.get(20)?,
-            data.get(21)?,
-            data.get(22)?,
-            data.get(23)?,
-        ]);
-        let symbol_val = Val::from_payload(symbol_bits);
-        let collision_type: Symbol = Symbol::try_from_val(env, &symbol_val).ok()?;
-        Some(Self {
-            entity_a,
-            entity_b,
-            collision_type,
-        })
-    }
 }
 
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, cougr_derive::Event)]
+#[event(name = "damage", entity = "target_entity")]
 pub struct DamageEvent {
     pub target_entity: u64,
     pub damage_amount: i32,
@@ -188,66 +844,141 @@ impl DamageEvent {
             damage_type,
         }
     }
-    fn to_string(&self) -> String {
-        let s = "damage"; // Simplified for now
-        String::from(s)
-    }
-}
-impl EventTrait for DamageEvent {
-    fn event_type() -> Symbol {
-        symbol_short!("damage")
-    }
-    fn serialize(&self, env: &Env) -> Bytes {
-        let mut bytes = Bytes::new(env);
-        bytes.append(&Bytes::from_slice(env, &self.target_entity.to_be_bytes()));
-        bytes.append(&Bytes::from_slice(env, &self.damage_amount.to_be_bytes()));
-        // Serialize the damage_type symbol by converting to Val and then to u64
-        let symbol_val: Val = self.damage_type.to_val();
-        let symbol_bits = symbol_val.get_payload();
-        bytes.append(&Bytes::from_slice(env, &symbol_bits.to_be_bytes()));
-        bytes
-    }
-    fn deserialize(env: &Env, data: &Bytes) -> Option<Self> {
-        if data.len() < 20 {
-            return None;
-        }
-        let target_entity = u64::from_be_bytes([
-            data.get(0)?,
-            data.get(1)?,
-            data.get(2)?,
-            data.get(3)?,
-            data.get(4)?,
-            data.get(5)?,
-            data.get(6)?,
-            data.get(7)?,
-        ]);
-        let damage_amount =
-            i32::from_be_bytes([data.get(8)?, data.get(9)?, data.get(10)?, data.get(11)?]);
-        // Deserialize the symbol from its Val representation
-        let symbol_bits = u64::from_be_bytes([
-            data.get(12)?,
-            data.get(13)?,
-            data.get(14)?,
-            data.get(15)?,
-            data.get(16)?,
-            data.get(17)?,
-            data.get(18)?,
-            data.get(19)?,
-        ]);
-        let symbol_val = Val::from_payload(symbol_bits);
-        let damage_type: Symbol = Symbol::try_from_val(env, &symbol_val).ok()?;
-        Some(Self {
-            target_entity,
-            damage_amount,
-            damage_type,
-        })
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use soroban_sdk::Env;
+    use crate::system::IntoSystem;
+    use soroban_sdk::testutils::{Events as _, Ledger as _};
+    use soroban_sdk::{contract, vec, Env, TryFromVal};
+
+    #[contract]
+    struct TestContract;
+
+    /// Event every client should see
+    #[derive(Clone, cougr_derive::Event)]
+    #[event(name = "levelup", publish, entity = "player")]
+    struct LevelUp {
+        player: u64,
+        level: u32,
+    }
+
+    #[test]
+    fn test_persisted_event_queue() {
+        let env = Env::default();
+        let contract_id = env.register(TestContract, ());
+        let attacks = symbol_short!("attacks");
+        let (ai, log) = (symbol_short!("ai"), symbol_short!("log"));
+
+        env.as_contract(&contract_id, || {
+            let mut queue = EventQueue::open(&env, attacks.clone()).with_capacity(2);
+            assert!(queue.send(&DamageEvent::new(1, 10, symbol_short!("fire")), 0));
+            assert!(queue.send(&DamageEvent::new(2, 20, symbol_short!("fire")), 0));
+            // Full: the oldest event is evicted
+            assert!(queue.send(&DamageEvent::new(3, 30, symbol_short!("ice")), 0));
+            assert_eq!(queue.len(), 2);
+            queue.flush();
+            assert!(!queue.is_dirty());
+        });
+
+        // The next transaction processes the attacks
+        env.as_contract(&contract_id, || {
+            let mut queue = EventQueue::open(&env, attacks.clone());
+            let damage = queue.read::<DamageEvent>(&ai, 1);
+            assert_eq!(damage.iter().map(|e| e.target_entity).sum::<u64>(), 5);
+            assert_eq!(queue.unread(&log), 2);
+            queue.flush();
+        });
+
+        env.as_contract(&contract_id, || {
+            let mut queue = EventQueue::open(&env, attacks.clone())
+                .with_capacity(4)
+                .with_overflow(OverflowPolicy::DropNewest);
+            // Cursors survive, so nothing is read twice
+            assert!(queue.read::<DamageEvent>(&ai, 1).is_empty());
+            assert_eq!(queue.read_events(&log, 1).len(), 2);
+
+            let ping = Event::new(symbol_short!("ping"), Bytes::new(&env));
+            assert!(queue.push_with_expiry(ping.clone(), 1, Expiry::Ticks(2)));
+            assert!(queue.push_with_expiry(ping.clone(), 1, Expiry::Ledgers(1)));
+            assert!(!queue.push(ping, 1));
+            assert_eq!(queue.len(), 4);
+            queue.flush();
+        });
+
+        env.ledger().with_mut(|ledger| ledger.sequence_number += 1);
+        env.as_contract(&contract_id, || {
+            let mut queue = EventQueue::open(&env, attacks.clone());
+            // The ledger-bound event expired, the tick-bound one lasts until tick 3
+            assert_eq!(queue.read_events(&ai, 2).len(), 1);
+            queue.prune(3);
+            assert_eq!(queue.len(), 2);
+            queue.flush();
+        });
+
+        // Reopening with a lower capacity evicts down to it on the next push
+        env.as_contract(&contract_id, || {
+            let mut queue = EventQueue::open(&env, attacks.clone()).with_capacity(1);
+            let ping = Event::new(symbol_short!("ping"), Bytes::new(&env));
+            assert!(queue.push(ping, 3));
+            assert_eq!(queue.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_event_queue_zero_capacity() {
+        let env = Env::default();
+        let contract_id = env.register(TestContract, ());
+        env.as_contract(&contract_id, || {
+            // Clamped to 1: each push evicts the previous event
+            let mut queue = EventQueue::open(&env, symbol_short!("attacks")).with_capacity(0);
+            let ping = Event::new(symbol_short!("ping"), Bytes::new(&env));
+            assert!(queue.push(ping.clone(), 0));
+            assert!(queue.push(ping, 0));
+            assert_eq!(queue.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_published_events() {
+        let env = Env::default();
+        let contract_id = env.register(TestContract, ());
+
+        env.as_contract(&contract_id, || {
+            let mut world = World::new(&env);
+            world.send(LevelUp {
+                player: 7,
+                level: 2,
+            });
+            // Not published unless the writer opts in
+            world.send(DamageEvent::new(3, 10, symbol_short!("fire")));
+            EventWriter::publishing().send(
+                &mut world,
+                CollisionEvent::new(1, 2, symbol_short!("physical")),
+            );
+            assert_eq!(world.read::<LevelUp>().count(), 1);
+        });
+
+        let published = env.events().all();
+        assert_eq!(published.len(), 2);
+        let (contract, topics, data) = published.get(0).unwrap();
+        assert_eq!(contract, contract_id);
+        let expected = (EVENT_TOPIC, symbol_short!("levelup"), 7u64).into_val(&env);
+        assert_eq!(topics, expected);
+        let data = Bytes::try_from_val(&env, &data).unwrap();
+        assert_eq!(LevelUp::deserialize(&env, &data).unwrap().level, 2);
+
+        let (_, topics, _) = published.get(1).unwrap();
+        let none: Option<u64> = None;
+        let expected: soroban_sdk::Vec<Val> = vec![
+            &env,
+            EVENT_TOPIC.into_val(&env),
+            symbol_short!("collision").into_val(&env),
+            none.into_val(&env),
+        ];
+        assert_eq!(topics, expected);
+    }
 
     #[test]
     fn test_event_creation() {
@@ -288,10 +1019,99 @@ mod tests {
         assert_eq!(damage_event.damage_type, deserialized.damage_type);
     }
 
-    // TODO: These tests require std vec! macro - need to adapt for Soroban
-    // #[test]
-    // fn test_event_reader() { ... }
+    #[test]
+    fn test_event_cursors() {
+        let mut events = Events::<u32>::new();
+        let mut early = events.get_cursor();
+        events.send(1);
+        let mut late = events.get_cursor_current();
+        events.send(2);
+
+        // Each cursor reads independently
+        assert_eq!(early.read(&events).copied().sum::<u32>(), 3);
+        assert_eq!(late.read(&events).copied().sum::<u32>(), 2);
+        assert_eq!(early.read(&events).count(), 0);
+
+        // Events survive one update and are dropped by the next
+        events.update();
+        events.send(3);
+        let mut fresh = events.get_cursor();
+        assert_eq!(fresh.len(&events), 3);
+        events.update();
+        assert_eq!(events.len(), 1);
+        assert_eq!(fresh.missed_events(&events), 2);
+        assert_eq!(
+            fresh.read(&events).copied().collect::<alloc::vec::Vec<_>>(),
+            [3]
+        );
+        assert_eq!(early.read(&events).copied().sum::<u32>(), 3);
+
+        // Reading another queue rebinds the cursor and starts it over there
+        let mut short = Events::<u32>::new();
+        short.send(4);
+        assert!(early.is_bound_to(&events) && !early.is_bound_to(&short));
+        assert_eq!(early.len(&short), 1);
+        assert_eq!(early.read(&short).copied().sum::<u32>(), 4);
+        assert!(early.is_bound_to(&short));
+        short.send(5);
+        assert_eq!(early.read(&short).copied().sum::<u32>(), 5);
+        // Going back starts over on the first queue, instead of resuming at
+        // the shorter queue's position
+        assert_eq!(early.len(&events), 1);
+        assert_eq!(early.read(&events).copied().sum::<u32>(), 3);
+        assert_eq!(early.read(&events).count(), 0);
+        assert_eq!(events.clone().get_cursor_current().len(&events), 0);
+    }
+
+    #[test]
+    fn test_typed_events() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let mut first = EventReader::<DamageEvent>::new();
+        let mut second = EventReader::<DamageEvent>::new();
+
+        world.send(DamageEvent::new(1, 10, symbol_short!("fire")));
+        let writer = EventWriter::<DamageEvent>::new();
+        writer.send(&mut world, DamageEvent::new(2, 5, symbol_short!("ice")));
+        world.send(CollisionEvent::new(1, 2, symbol_short!("physical")));
 
-    // #[test]
-    // fn test_event_writer() { ... }
+        let total: i32 = first.read(&world).map(|e| e.damage_amount).sum();
+        assert_eq!(total, 15);
+        assert!(first.is_empty(&world));
+        assert_eq!(second.len(&world), 2);
+        assert_eq!(second.read(&world).next().unwrap().target_entity, 1);
+
+        world.send(DamageEvent::new(3, 1, symbol_short!("fire")));
+        assert_eq!(first.read(&world).count(), 1);
+        assert_eq!(world.read::<DamageEvent>().count(), 3);
+        assert_eq!(world.read::<CollisionEvent>().count(), 1);
+    }
+
+    #[test]
+    fn test_event_reader_system_param() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let (ai, log) = (symbol_short!("ai"), symbol_short!("log"));
+        let mut system = (|world: &mut World, _: ()| -> i32 {
+            let mut reader = EventReader::<DamageEvent>::fetch_mut(world);
+            reader.read(world).map(|e| e.damage_amount).sum()
+        })
+        .into_system();
+
+        world.send(DamageEvent::new(1, 10, symbol_short!("fire")));
+        world.send(DamageEvent::new(2, 5, symbol_short!("ice")));
+        assert_eq!(world.run_system(ai.clone(), &mut system, ()), 15);
+        assert_eq!(world.run_system(ai.clone(), &mut system, ()), 0);
+
+        world.advance_tick();
+        world.send(DamageEvent::new(3, 1, symbol_short!("fire")));
+        assert_eq!(world.run_system(ai.clone(), &mut system, ()), 1);
+        assert_eq!(world.run_system(ai, &mut system, ()), 0);
+
+        // Each system name has its own cursor
+        assert_eq!(world.run_system(log.clone(), &mut system, ()), 16);
+        assert_eq!(world.run_system(log, &mut system, ()), 0);
+        // Fetched outside a system, a reader starts at the oldest event
+        assert_eq!(EventReader::<DamageEvent>::fetch(&world).len(&world), 3);
+    }
 }
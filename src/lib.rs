@@ -2,14 +2,19 @@
 #![allow(unsafe_code)]
 
 extern crate alloc;
+// Lets the `cougr-derive` output name `::cougr_core` from inside this crate too
+extern crate self as cougr_core;
 
-use soroban_sdk::{symbol_short, Bytes, Symbol, Vec};
+use soroban_sdk::{Env, Symbol, Vec};
 
 // Global allocator for WASM
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 // Core ECS types adapted for Soroban
+pub mod archetype;
+pub mod change;
+pub mod codec;
 pub mod component;
 pub mod components;
 pub mod entity;
@@ -22,20 +27,29 @@ pub mod systems;
 pub mod world;
 
 // Re-export core types
-pub use component::{Component, ComponentId, ComponentStorage};
+pub use archetype::{Archetype, ArchetypeId, Archetypes};
+pub use change::ChangeTracker;
+pub use component::{Bundle, Component, ComponentId, ComponentStorage, ComponentTrait, Durability};
 pub use components::Position;
-pub use entity::{Entity, EntityId};
-pub use event::{Event, EventReader, EventWriter};
-pub use query::{Query, QueryState};
-pub use resource::Resource;
-pub use storage::{SparseStorage, Storage, TableStorage};
+pub use cougr_derive::{Codec, Component, Event, Resource};
+pub use entity::{Entity, EntityId, EntityMut};
+pub use event::{
+    Event, EventCursor, EventQueue, EventReader, EventTrait, EventWriter, Events, Expiry,
+    OverflowPolicy,
+};
+pub use query::{
+    And, Mut, Not, Or, Query, QueryBuilder, QueryData, QueryFilter, QueryMask, QueryMut,
+    QueryState, Xor,
+};
+pub use resource::{ResMut, Resource, ResourceMut, ResourceTrait};
+pub use storage::{ComponentMut, PersistentWorld, SparseStorage, Storage, TableStorage, TtlPolicy};
 pub use system::{IntoSystem, System, SystemParam};
 pub use systems::MovementSystem;
 pub use world::World;
 
 // Library functions for ECS operations
-pub fn create_world() -> World {
-    World::new()
+pub fn create_world(env: &Env) -> World {
+    World::new(env)
 }
 
 pub fn spawn_entity(world: &mut World, components: Vec<Component>) -> EntityId {
@@ -44,8 +58,7 @@ pub fn spawn_entity(world: &mut World, components: Vec<Component>) -> EntityId {
 }
 
 pub fn add_component(world: &mut World, entity_id: EntityId, component: Component) -> bool {
-    world.add_component_to_entity(entity_id, component);
-    true
+    world.add_component_to_entity(entity_id, component)
 }
 
 pub fn remove_component(world: &mut World, entity_id: EntityId, component_type: Symbol) -> bool {
@@ -60,25 +73,25 @@ pub fn get_component(
     world.get_component(entity_id, &component_type)
 }
 
-pub fn query_entities(
-    world: &World,
-    component_types: Vec<Symbol>,
-    env: &soroban_sdk::Env,
-) -> Vec<EntityId> {
-    // Since we can't easily convert Vec<Symbol> to &[Symbol] in Soroban,
-    // we'll need to restructure this. For now, return empty result.
-    Vec::new(env)
+pub fn query_entities(world: &World, component_types: Vec<Symbol>) -> Vec<EntityId> {
+    let mut query = Query::new(world.env());
+    for component_type in component_types.iter() {
+        query = query.with_component(component_type);
+    }
+    query.execute(world)
 }
 
 // Predule for common types
 pub mod prelude {
+    pub use cougr_derive::{Codec, Component, Event, Resource};
+
     pub use super::{
-        component::{Component, ComponentId, ComponentStorage},
-        entity::{Entity, EntityId},
-        event::{Event, EventReader, EventWriter},
-        query::{Query, QueryState},
-        resource::Resource,
-        storage::{SparseStorage, Storage, TableStorage},
+        component::{Bundle, Component, ComponentId, ComponentStorage, ComponentTrait, Durability},
+        entity::{Entity, EntityId, EntityMut},
+        event::{Event, EventCursor, EventReader, EventTrait, EventWriter, Events},
+        query::{Mut, Query, QueryData, QueryMut, QueryState},
+        resource::{ResMut, Resource, ResourceMut, ResourceTrait},
+        storage::{ComponentMut, PersistentWorld, SparseStorage, Storage, TableStorage, TtlPolicy},
         system::{IntoSystem, System, SystemParam},
         world::World,
     };
@@ -87,20 +100,32 @@ pub mod prelude {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use soroban_sdk::{testutils::Address as _, Env};
+    use soroban_sdk::Env;
 
     #[test]
     fn test_world_creation() {
-        let _env = Env::default();
-        let world = World::new();
+        let env = Env::default();
+        let world = create_world(&env);
         assert_eq!(world.entity_count(), 0);
     }
 
+    #[test]
+    fn test_add_component_reports_failure() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let entity_id = world.spawn_empty().id();
+        let position = component::Position::new(1, 2);
+        let component = Component::from_typed(&env, &position);
+        assert!(add_component(&mut world, entity_id, component.clone()));
+        world.despawn(entity_id);
+        assert!(!add_component(&mut world, entity_id, component));
+    }
+
     #[test]
     fn test_entity_spawn() {
-        let _env = Env::default();
-        let mut world = World::new();
-        let entity = world.spawn_empty();
+        let env = Env::default();
+        let mut world = World::new(&env);
+        world.spawn_empty();
         assert_eq!(world.entity_count(), 1);
     }
 }
@@ -1,24 +1,60 @@
+use crate::component::{Component, ComponentMask, ComponentRegistry, ComponentTrait};
 use crate::entity::EntityId;
 use crate::world::World;
 use alloc::boxed::Box;
-use soroban_sdk::{Symbol, Vec};
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::rc::Rc;
+use alloc::vec::Vec as StdVec;
+use core::fmt;
+use core::iter::Sum;
+use core::marker::PhantomData;
+use core::ops::AddAssign;
+use soroban_sdk::{Env, Symbol, Vec};
+
+/// Sorts a query's matching entities in place
+type SortFn = dyn Fn(&World, &mut StdVec<EntityId>);
 
 /// A query for entities with specific components
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Query {
     /// Required component types
     pub required_components: Vec<Symbol>,
     /// Excluded component types
     pub excluded_components: Vec<Symbol>,
+    /// Filters every candidate entity must also pass
+    filters: StdVec<Rc<dyn QueryFilter>>,
+    /// Sorts the filtered entities
+    order: Option<Rc<SortFn>>,
+    /// Number of leading results to skip
+    offset: u32,
+    /// Maximum number of results
+    limit: Option<u32>,
+}
+
+impl fmt::Debug for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Query")
+            .field("required_components", &self.required_components)
+            .field("excluded_components", &self.excluded_components)
+            .field("filters", &self.filters.len())
+            .field("ordered", &self.order.is_some())
+            .field("offset", &self.offset)
+            .field("limit", &self.limit)
+            .finish()
+    }
 }
 
 impl Query {
     /// Create a new query
-    pub fn new() -> Self {
-        let env = soroban_sdk::Env::default();
+    pub fn new(env: &Env) -> Self {
         Self {
-            required_components: Vec::new(&env),
-            excluded_components: Vec::new(&env),
+            required_components: Vec::new(env),
+            excluded_components: Vec::new(env),
+            filters: StdVec::new(),
+            order: None,
+            offset: 0,
+            limit: None,
         }
     }
 
@@ -34,70 +70,540 @@ impl Query {
         self
     }
 
+    /// Add a filter every matching entity must pass
+    ///
+    /// Filters run per entity, after the component requirements have
+    /// narrowed the candidates down to the matching archetypes.
+    pub fn filter<F: QueryFilter + 'static>(mut self, filter: F) -> Self {
+        self.filters.push(Rc::new(filter));
+        self
+    }
+
+    /// Require component `T` and a predicate on its decoded value
+    ///
+    /// Only `T` is decoded, and only for entities that already passed the
+    /// component requirements.
+    pub fn where_component<T, P>(self, predicate: P) -> Self
+    where
+        T: ComponentTrait + 'static,
+        P: Fn(&T) -> bool + 'static,
+    {
+        let env = self.required_components.env().clone();
+        self.with_component(T::component_type(&env))
+            .filter(ComponentPredicate {
+                predicate,
+                marker: PhantomData,
+            })
+    }
+
+    /// Require component `T` and sort results by a key of its value
+    ///
+    /// Sorting is ascending; wrap the key in [`core::cmp::Reverse`] for
+    /// descending order. Each entity's key is decoded once.
+    pub fn order_by<T, K, F>(self, key: F) -> Self
+    where
+        T: ComponentTrait + 'static,
+        K: Ord,
+        F: Fn(&T) -> K + 'static,
+    {
+        let env = self.required_components.env().clone();
+        let mut query = self.with_component(T::component_type(&env));
+        query.order = Some(Rc::new(move |world, entities| {
+            entities.sort_by_cached_key(|&entity_id| {
+                world.get::<T>(entity_id).map(|value| key(&value))
+            });
+        }));
+        query
+    }
+
+    /// Skip the first `offset` results
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Return at most `limit` results
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
     /// Execute the query on a world
+    ///
+    /// Each archetype's component set is tested once; every entity of a
+    /// matching archetype is part of the result, unless a filter rejects it.
+    /// Ordering, `offset` and `limit` apply last.
     pub fn execute(&self, world: &World) -> Vec<EntityId> {
-        let env = soroban_sdk::Env::default();
-        let mut results = Vec::new(&env);
-
-        for entity in world.iter_entities() {
-            // Check if entity has all required components
-            let has_required = self
-                .required_components
-                .iter()
-                .all(|component_type| entity.has_component(&component_type));
-
-            // Check if entity has none of the excluded components
-            let has_excluded = self
-                .excluded_components
-                .iter()
-                .any(|component_type| entity.has_component(&component_type));
-
-            if has_required && !has_excluded {
-                results.push_back(entity.id());
+        if self.is_plain() {
+            return match self.compile(&world.components) {
+                Some(mask) => world.archetypes.query(mask.required, mask.excluded),
+                None => Vec::new(world.env()),
+            };
+        }
+        let mut results = Vec::new(world.env());
+        for entity_id in self.iter_entities(world) {
+            results.push_back(entity_id);
+        }
+        results
+    }
+
+    /// Iterate over the matching entities without collecting them
+    ///
+    /// Entities are streamed straight out of the matching archetypes; only
+    /// an [`order_by`](Query::order_by) query has to gather them to sort.
+    pub fn iter_entities<'a>(
+        &'a self,
+        world: &'a World,
+    ) -> Box<dyn Iterator<Item = EntityId> + 'a> {
+        let mask = match self.compile(&world.components) {
+            Some(mask) => mask,
+            None => return Box::new(core::iter::empty()),
+        };
+        let matching = world
+            .archetypes
+            .matching(mask.required, mask.excluded)
+            .flat_map(|archetype| archetype.entities().iter())
+            .filter(move |&entity_id| self.matches_filters(world, entity_id));
+        let offset = self.offset as usize;
+        let limit = self.limit.map_or(usize::MAX, |limit| limit as usize);
+        match &self.order {
+            Some(order) => {
+                let mut sorted: StdVec<EntityId> = matching.collect();
+                order(world, &mut sorted);
+                Box::new(sorted.into_iter().skip(offset).take(limit))
             }
+            None => Box::new(matching.skip(offset).take(limit)),
         }
+    }
 
-        results
+    /// Count the matching entities
+    pub fn count(&self, world: &World) -> u32 {
+        if !self.is_plain() {
+            return self.iter_entities(world).count() as u32;
+        }
+        match self.compile(&world.components) {
+            Some(mask) => world
+                .archetypes
+                .matching(mask.required, mask.excluded)
+                .map(|archetype| archetype.len() as u32)
+                .sum(),
+            None => 0,
+        }
+    }
+
+    /// Sum a value of component `T` over the matching entities
+    ///
+    /// Entities without `T` are skipped.
+    pub fn sum_by<T: ComponentTrait, N: Sum<N>>(
+        &self,
+        world: &World,
+        value: impl Fn(&T) -> N,
+    ) -> N {
+        sum_by(world, self.iter_entities(world), value)
+    }
+
+    /// Find the entity whose component `T` has the smallest key
+    pub fn min_by_key<T: ComponentTrait, K: Ord>(
+        &self,
+        world: &World,
+        key: impl Fn(&T) -> K,
+    ) -> Option<(EntityId, T)> {
+        decoded::<T>(world, self.iter_entities(world)).min_by_key(|(_, value)| key(value))
+    }
+
+    /// Find the entity whose component `T` has the largest key
+    pub fn max_by_key<T: ComponentTrait, K: Ord>(
+        &self,
+        world: &World,
+        key: impl Fn(&T) -> K,
+    ) -> Option<(EntityId, T)> {
+        decoded::<T>(world, self.iter_entities(world)).max_by_key(|(_, value)| key(value))
+    }
+
+    /// Count the matching entities per key of their component `G`
+    pub fn group_by<G: ComponentTrait, K: Ord>(
+        &self,
+        world: &World,
+        key: impl Fn(&G) -> K,
+    ) -> BTreeMap<K, u32> {
+        group_by(world, self.iter_entities(world), key)
+    }
+
+    /// Sum a value of component `T` per key of component `G`
+    ///
+    /// Entities missing either component are skipped.
+    pub fn group_sum_by<G: ComponentTrait, T: ComponentTrait, K: Ord, N: AddAssign>(
+        &self,
+        world: &World,
+        key: impl Fn(&G) -> K,
+        value: impl Fn(&T) -> N,
+    ) -> BTreeMap<K, N> {
+        group_sum_by(world, self.iter_entities(world), key, value)
+    }
+
+    /// Check if the query is answered by archetype masks alone
+    fn is_plain(&self) -> bool {
+        self.filters.is_empty() && self.order.is_none() && self.offset == 0 && self.limit.is_none()
+    }
+
+    fn matches_filters(&self, world: &World, entity_id: EntityId) -> bool {
+        self.filters
+            .iter()
+            .all(|filter| filter.matches(world, entity_id))
+    }
+
+    /// Iterate over the decoded data of every matching entity
+    ///
+    /// The components `D` fetches are added to the query's required types.
+    pub fn iter<'w, D: QueryData>(&self, world: &'w World) -> impl Iterator<Item = D::Item> + 'w {
+        let query = D::require(world.env(), self.clone());
+        query
+            .execute(world)
+            .into_iter()
+            .filter_map(move |entity_id| D::fetch(world, entity_id))
+    }
+
+    /// Decode the data of every matching entity for in-place modification
+    ///
+    /// The [`Mut`] components of each item are written back when the
+    /// returned [`QueryMut`] is dropped.
+    pub fn iter_mut<'w, D: QueryData>(&self, world: &'w mut World) -> QueryMut<'w, D> {
+        let query = D::require(world.env(), self.clone());
+        let mut items = StdVec::new();
+        for entity_id in query.execute(world) {
+            if let Some(item) = D::fetch(world, entity_id) {
+                items.push((entity_id, item));
+            }
+        }
+        QueryMut { world, items }
+    }
+
+    /// Compile the query into bitmask tests against a registry
+    ///
+    /// Returns `None` if a required type was never registered, in which case
+    /// no entity can match. Unregistered excluded types are ignored.
+    pub fn compile(&self, registry: &ComponentRegistry) -> Option<QueryMask> {
+        let required = registry.mask_of(&self.required_components)?;
+        let mut excluded = ComponentMask::new();
+        for component_type in self.excluded_components.iter() {
+            if let Some(bit) = registry.bit_index(&component_type) {
+                excluded = excluded.with(bit);
+            }
+        }
+        Some(QueryMask { required, excluded })
     }
 
     /// Check if the query is empty (no requirements)
     pub fn is_empty(&self) -> bool {
-        self.required_components.is_empty() && self.excluded_components.is_empty()
+        self.required_components.is_empty()
+            && self.excluded_components.is_empty()
+            && self.filters.is_empty()
+    }
+}
+
+/// Decode component `T` of each entity, skipping entities without it
+fn decoded<'a, T: ComponentTrait>(
+    world: &'a World,
+    entities: impl Iterator<Item = EntityId> + 'a,
+) -> impl Iterator<Item = (EntityId, T)> + 'a {
+    entities.filter_map(move |entity_id| Some((entity_id, world.get::<T>(entity_id)?)))
+}
+
+fn sum_by<T: ComponentTrait, N: Sum<N>>(
+    world: &World,
+    entities: impl Iterator<Item = EntityId>,
+    value: impl Fn(&T) -> N,
+) -> N {
+    decoded::<T>(world, entities)
+        .map(|(_, component)| value(&component))
+        .sum()
+}
+
+fn group_by<G: ComponentTrait, K: Ord>(
+    world: &World,
+    entities: impl Iterator<Item = EntityId>,
+    key: impl Fn(&G) -> K,
+) -> BTreeMap<K, u32> {
+    let mut groups = BTreeMap::new();
+    for (_, group) in decoded::<G>(world, entities) {
+        *groups.entry(key(&group)).or_insert(0) += 1;
     }
+    groups
 }
 
-impl Default for Query {
-    fn default() -> Self {
-        Self::new()
+fn group_sum_by<G: ComponentTrait, T: ComponentTrait, K: Ord, N: AddAssign>(
+    world: &World,
+    entities: impl Iterator<Item = EntityId>,
+    key: impl Fn(&G) -> K,
+    value: impl Fn(&T) -> N,
+) -> BTreeMap<K, N> {
+    let mut groups = BTreeMap::new();
+    for (entity_id, group) in decoded::<G>(world, entities) {
+        if let Some(component) = world.get::<T>(entity_id) {
+            let value = value(&component);
+            match groups.entry(key(&group)) {
+                Entry::Occupied(mut total) => *total.get_mut() += value,
+                Entry::Vacant(total) => {
+                    total.insert(value);
+                }
+            }
+        }
+    }
+    groups
+}
+
+/// Filter testing a predicate on an entity's decoded component
+struct ComponentPredicate<T, P> {
+    predicate: P,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: ComponentTrait, P: Fn(&T) -> bool> QueryFilter for ComponentPredicate<T, P> {
+    fn matches(&self, world: &World, entity_id: EntityId) -> bool {
+        world
+            .get::<T>(entity_id)
+            .is_some_and(|value| (self.predicate)(&value))
+    }
+}
+
+/// A query compiled into required and excluded component bitmasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryMask {
+    pub required: ComponentMask,
+    pub excluded: ComponentMask,
+}
+
+impl QueryMask {
+    /// Check if an entity or archetype signature satisfies the query
+    pub fn matches(&self, signature: ComponentMask) -> bool {
+        signature.contains_all(self.required) && !signature.intersects(self.excluded)
+    }
+}
+
+/// Component data a typed query fetches for each matching entity
+///
+/// Implemented for any [`ComponentTrait`] type (decoded by value), for
+/// [`Mut<T>`] (decoded, then written back), for [`EntityId`], and for tuples
+/// of these.
+pub trait QueryData {
+    /// The value yielded per entity
+    type Item;
+
+    /// Add the component types this fetches to a query's required types
+    fn require(env: &Env, query: Query) -> Query;
+
+    /// Decode this data for one entity
+    fn fetch(world: &World, entity_id: EntityId) -> Option<Self::Item>;
+
+    /// Persist any mutable parts of an item back into the world
+    fn write_back(_world: &mut World, _entity_id: EntityId, _item: &Self::Item) {}
+}
+
+impl<T: ComponentTrait> QueryData for T {
+    type Item = T;
+
+    fn require(env: &Env, query: Query) -> Query {
+        query.with_component(T::component_type(env))
+    }
+
+    fn fetch(world: &World, entity_id: EntityId) -> Option<Self::Item> {
+        world.get::<T>(entity_id)
+    }
+}
+
+/// Marks a component a typed query writes back once iteration ends
+pub struct Mut<T>(PhantomData<T>);
+
+impl<T: ComponentTrait> QueryData for Mut<T> {
+    type Item = T;
+
+    fn require(env: &Env, query: Query) -> Query {
+        query.with_component(T::component_type(env))
+    }
+
+    fn fetch(world: &World, entity_id: EntityId) -> Option<Self::Item> {
+        world.get::<T>(entity_id)
+    }
+
+    /// Unchanged items are not written, so they record no change
+    fn write_back(world: &mut World, entity_id: EntityId, item: &Self::Item) {
+        let component = Component::from_typed(world.env(), item);
+        let component_type = component.component_type().clone();
+        if let Some(mut stored) = world.get_component_mut(entity_id, &component_type) {
+            stored.data = component.data;
+            stored.version = component.version;
+        }
+    }
+}
+
+impl QueryData for EntityId {
+    type Item = EntityId;
+
+    fn require(_env: &Env, query: Query) -> Query {
+        query
+    }
+
+    fn fetch(_world: &World, entity_id: EntityId) -> Option<Self::Item> {
+        Some(entity_id)
+    }
+}
+
+macro_rules! impl_query_data_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: QueryData),+> QueryData for ($($name,)+) {
+            type Item = ($($name::Item,)+);
+
+            fn require(env: &Env, query: Query) -> Query {
+                $(let query = $name::require(env, query);)+
+                query
+            }
+
+            fn fetch(world: &World, entity_id: EntityId) -> Option<Self::Item> {
+                Some(($($name::fetch(world, entity_id)?,)+))
+            }
+
+            #[allow(non_snake_case)]
+            fn write_back(world: &mut World, entity_id: EntityId, item: &Self::Item) {
+                let ($($name,)+) = item;
+                $($name::write_back(world, entity_id, $name);)+
+            }
+        }
+    };
+}
+
+impl_query_data_for_tuple!(A);
+impl_query_data_for_tuple!(A, B);
+impl_query_data_for_tuple!(A, B, C);
+impl_query_data_for_tuple!(A, B, C, D);
+impl_query_data_for_tuple!(A, B, C, D, E);
+impl_query_data_for_tuple!(A, B, C, D, E, F);
+impl_query_data_for_tuple!(A, B, C, D, E, F, G);
+impl_query_data_for_tuple!(A, B, C, D, E, F, G, H);
+
+/// Decoded results of a typed query over a mutable world
+///
+/// Items can be changed in place; the [`Mut`] parts of every item are
+/// written back to the world when this is dropped.
+pub struct QueryMut<'w, D: QueryData> {
+    world: &'w mut World,
+    items: StdVec<(EntityId, D::Item)>,
+}
+
+impl<D: QueryData> QueryMut<'_, D> {
+    /// Iterate over the items
+    pub fn iter(&self) -> impl Iterator<Item = &D::Item> + '_ {
+        self.items.iter().map(|(_, item)| item)
+    }
+
+    /// Iterate over the items mutably
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut D::Item> + '_ {
+        self.items.iter_mut().map(|(_, item)| item)
+    }
+
+    /// Get the number of matching entities
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Check if no entity matched
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<D: QueryData> Drop for QueryMut<'_, D> {
+    fn drop(&mut self) {
+        for (entity_id, item) in &self.items {
+            D::write_back(self.world, *entity_id, item);
+        }
     }
 }
 
 /// Query state for tracking query results
+///
+/// The results are cached against the world's
+/// [change versions](crate::change::ChangeTracker): executing again reuses
+/// them while none of the query's component types changed, and patches them
+/// with just the entities that moved when only a few did.
 #[derive(Debug, Clone)]
 pub struct QueryState {
     query: Query,
     last_results: Vec<EntityId>,
     last_execution_time: u64,
+    /// World change version the results are current for, once executed
+    last_version: Option<u64>,
+    /// Registered component types when the results were computed
+    last_component_count: usize,
 }
 
 impl QueryState {
     /// Create a new query state
     pub fn new(query: Query) -> Self {
-        let env = soroban_sdk::Env::default();
+        let last_results = Vec::new(query.required_components.env());
         Self {
             query,
-            last_results: Vec::new(&env),
+            last_results,
             last_execution_time: 0,
+            last_version: None,
+            last_component_count: 0,
         }
     }
 
     /// Execute the query and update state
+    ///
+    /// Cached results are reused if nothing the query depends on changed.
+    /// Otherwise the entities changed since the last execution are
+    /// re-checked, unless the query is ordered or paged, or too many
+    /// changes happened, in which case it runs again in full.
     pub fn execute(&mut self, world: &World) -> &Vec<EntityId> {
-        self.last_results = self.query.execute(world);
-        self.last_execution_time = 0; // In a real implementation, this would be the current time
+        if self.needs_update(world) && !self.patch(world) {
+            self.last_results = self.query.execute(world);
+        }
+        self.last_version = Some(world.changes.version());
+        self.last_component_count = world.component_count();
+        self.last_execution_time = world.env().ledger().timestamp();
         &self.last_results
     }
 
+    /// Re-check the entities changed since the last execution
+    ///
+    /// Returns `false` if the results cannot be patched.
+    fn patch(&mut self, world: &World) -> bool {
+        let query = &self.query;
+        if query.order.is_some() || query.offset != 0 || query.limit.is_some() {
+            return false;
+        }
+        let (version, mask) = match (self.last_version, query.compile(&world.components)) {
+            (Some(version), Some(mask)) => (version, mask),
+            _ => return false,
+        };
+        if self.last_component_count != world.component_count() {
+            return false;
+        }
+        let changed: BTreeSet<EntityId> = match world.changes.entities_since(version) {
+            Some(changed) => changed.collect(),
+            None => return false,
+        };
+        // Walk the matching archetypes like `Query::execute`, so the patched
+        // results keep its order; only changed entities re-run the filters
+        let previous: BTreeSet<EntityId> = self.last_results.iter().collect();
+        let mut results = Vec::new(world.env());
+        for archetype in world.archetypes.matching(mask.required, mask.excluded) {
+            for entity_id in archetype.entities().iter() {
+                let matches = if changed.contains(&entity_id) {
+                    query.matches_filters(world, entity_id)
+                } else {
+                    previous.contains(&entity_id)
+                };
+                if matches {
+                    results.push_back(entity_id);
+                }
+            }
+        }
+        self.last_results = results;
+        true
+    }
+
     /// Get the last query results
     pub fn results(&self) -> &Vec<EntityId> {
         &self.last_results
@@ -113,16 +619,86 @@ impl QueryState {
         self.last_results.len().try_into().unwrap()
     }
 
-    /// Get the last execution time
+    /// Get the ledger timestamp of the last execution
     pub fn last_execution_time(&self) -> u64 {
         self.last_execution_time
     }
 
-    /// Check if the query needs to be re-executed
-    pub fn needs_update(&self, current_time: u64) -> bool {
-        // In a real implementation, you might check if the world has changed
-        // For now, we'll just return true to always re-execute
-        true
+    /// Get the world change version the results are current for
+    pub fn last_version(&self) -> Option<u64> {
+        self.last_version
+    }
+
+    /// Check if the world changed in a way that can affect the results
+    ///
+    /// Plain component queries only depend on their required and excluded
+    /// types (and on spawns and despawns when nothing is required). Filtered,
+    /// ordered or paged queries depend on any change.
+    pub fn needs_update(&self, world: &World) -> bool {
+        let version = match self.last_version {
+            Some(version) => version,
+            None => return true,
+        };
+        let changes = &world.changes;
+        if changes.version() == version {
+            return false;
+        }
+        if self.last_component_count != world.component_count() || !self.query.is_plain() {
+            return true;
+        }
+        match self.query.compile(&world.components) {
+            Some(mask) => {
+                changes.changed_since(mask.required.union(mask.excluded), version)
+                    || (mask.required.is_empty() && changes.entities_version() > version)
+            }
+            None => false,
+        }
+    }
+
+    /// Sum a value of component `T` over the last results
+    pub fn sum_by<T: ComponentTrait, N: Sum<N>>(
+        &self,
+        world: &World,
+        value: impl Fn(&T) -> N,
+    ) -> N {
+        sum_by(world, self.last_results.iter(), value)
+    }
+
+    /// Find the result whose component `T` has the smallest key
+    pub fn min_by_key<T: ComponentTrait, K: Ord>(
+        &self,
+        world: &World,
+        key: impl Fn(&T) -> K,
+    ) -> Option<(EntityId, T)> {
+        decoded::<T>(world, self.last_results.iter()).min_by_key(|(_, value)| key(value))
+    }
+
+    /// Find the result whose component `T` has the largest key
+    pub fn max_by_key<T: ComponentTrait, K: Ord>(
+        &self,
+        world: &World,
+        key: impl Fn(&T) -> K,
+    ) -> Option<(EntityId, T)> {
+        decoded::<T>(world, self.last_results.iter()).max_by_key(|(_, value)| key(value))
+    }
+
+    /// Count the last results per key of their component `G`
+    pub fn group_by<G: ComponentTrait, K: Ord>(
+        &self,
+        world: &World,
+        key: impl Fn(&G) -> K,
+    ) -> BTreeMap<K, u32> {
+        group_by(world, self.last_results.iter(), key)
+    }
+
+    /// Sum a value of component `T` per key of component `G` over the last results
+    pub fn group_sum_by<G: ComponentTrait, T: ComponentTrait, K: Ord, N: AddAssign>(
+        &self,
+        world: &World,
+        key: impl Fn(&G) -> K,
+        value: impl Fn(&T) -> N,
+    ) -> BTreeMap<K, N> {
+        group_sum_by(world, self.last_results.iter(), key, value)
     }
 }
 
@@ -133,9 +709,9 @@ pub struct QueryBuilder {
 
 impl QueryBuilder {
     /// Create a new query builder
-    pub fn new() -> Self {
+    pub fn new(env: &Env) -> Self {
         Self {
-            query: Query::new(),
+            query: Query::new(env),
         }
     }
 
@@ -167,6 +743,45 @@ impl QueryBuilder {
         self
     }
 
+    /// Add a filter, such as an [`And`]/[`Or`]/[`Not`]/[`Xor`] tree
+    pub fn filter<F: QueryFilter + 'static>(mut self, filter: F) -> Self {
+        self.query = self.query.filter(filter);
+        self
+    }
+
+    /// Require component `T` and a predicate on its decoded value
+    pub fn where_component<T, P>(mut self, predicate: P) -> Self
+    where
+        T: ComponentTrait + 'static,
+        P: Fn(&T) -> bool + 'static,
+    {
+        self.query = self.query.where_component(predicate);
+        self
+    }
+
+    /// Require component `T` and sort results by a key of its value
+    pub fn order_by<T, K, F>(mut self, key: F) -> Self
+    where
+        T: ComponentTrait + 'static,
+        K: Ord,
+        F: Fn(&T) -> K + 'static,
+    {
+        self.query = self.query.order_by(key);
+        self
+    }
+
+    /// Skip the first `offset` results
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.query = self.query.offset(offset);
+        self
+    }
+
+    /// Return at most `limit` results
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.query = self.query.limit(limit);
+        self
+    }
+
     /// Build the final query
     pub fn build(self) -> Query {
         self.query
@@ -178,16 +793,140 @@ impl QueryBuilder {
     }
 }
 
-impl Default for QueryBuilder {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 /// Query filter for more complex querying
+///
+/// Filters combine into boolean trees with [`and`](QueryFilter::and),
+/// [`or`](QueryFilter::or), [`xor`](QueryFilter::xor) and [`Not`]:
+///
+/// ```ignore
+/// // Enemies that are (frozen or stunned) and not a boss
+/// let query = QueryBuilder::new(&env)
+///     .with_component(symbol_short!("enemy"))
+///     .filter(
+///         WithComponent::new(symbol_short!("frozen"))
+///             .or(WithComponent::new(symbol_short!("stunned")))
+///             .and(Not::new(WithComponent::new(symbol_short!("boss")))),
+///     )
+///     .build();
+/// ```
 pub trait QueryFilter {
     /// Check if an entity matches this filter
     fn matches(&self, world: &World, entity_id: EntityId) -> bool;
+
+    /// Match entities passing both filters
+    fn and<F: QueryFilter>(self, other: F) -> And<Self, F>
+    where
+        Self: Sized,
+    {
+        And::new(self, other)
+    }
+
+    /// Match entities passing either filter
+    fn or<F: QueryFilter>(self, other: F) -> Or<Self, F>
+    where
+        Self: Sized,
+    {
+        Or::new(self, other)
+    }
+
+    /// Match entities passing exactly one of the filters
+    fn xor<F: QueryFilter>(self, other: F) -> Xor<Self, F>
+    where
+        Self: Sized,
+    {
+        Xor::new(self, other)
+    }
+}
+
+impl<F: QueryFilter + ?Sized> QueryFilter for &F {
+    fn matches(&self, world: &World, entity_id: EntityId) -> bool {
+        (**self).matches(world, entity_id)
+    }
+}
+
+impl<F: QueryFilter + ?Sized> QueryFilter for alloc::boxed::Box<F> {
+    fn matches(&self, world: &World, entity_id: EntityId) -> bool {
+        (**self).matches(world, entity_id)
+    }
+}
+
+/// Filter matching entities that pass both sub-filters
+///
+/// The right filter is only evaluated when the left one matches.
+pub struct And<A, B> {
+    left: A,
+    right: B,
+}
+
+impl<A: QueryFilter, B: QueryFilter> And<A, B> {
+    /// Create a new filter
+    pub fn new(left: A, right: B) -> Self {
+        Self { left, right }
+    }
+}
+
+impl<A: QueryFilter, B: QueryFilter> QueryFilter for And<A, B> {
+    fn matches(&self, world: &World, entity_id: EntityId) -> bool {
+        self.left.matches(world, entity_id) && self.right.matches(world, entity_id)
+    }
+}
+
+/// Filter matching entities that pass either sub-filter
+///
+/// The right filter is only evaluated when the left one fails.
+pub struct Or<A, B> {
+    left: A,
+    right: B,
+}
+
+impl<A: QueryFilter, B: QueryFilter> Or<A, B> {
+    /// Create a new filter
+    pub fn new(left: A, right: B) -> Self {
+        Self { left, right }
+    }
+}
+
+impl<A: QueryFilter, B: QueryFilter> QueryFilter for Or<A, B> {
+    fn matches(&self, world: &World, entity_id: EntityId) -> bool {
+        self.left.matches(world, entity_id) || self.right.matches(world, entity_id)
+    }
+}
+
+/// Filter matching entities that pass exactly one sub-filter
+pub struct Xor<A, B> {
+    left: A,
+    right: B,
+}
+
+impl<A: QueryFilter, B: QueryFilter> Xor<A, B> {
+    /// Create a new filter
+    pub fn new(left: A, right: B) -> Self {
+        Self { left, right }
+    }
+}
+
+impl<A: QueryFilter, B: QueryFilter> QueryFilter for Xor<A, B> {
+    fn matches(&self, world: &World, entity_id: EntityId) -> bool {
+        self.left.matches(world, entity_id) != self.right.matches(world, entity_id)
+    }
+}
+
+/// Filter matching entities that fail the inner filter
+pub struct Not<F> {
+    inner: F,
+}
+
+impl<F: QueryFilter> Not<F> {
+    /// Create a new filter
+    pub fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+impl<F: QueryFilter> QueryFilter for Not<F> {
+    fn matches(&self, world: &World, entity_id: EntityId) -> bool {
+        !self.inner.matches(world, entity_id)
+    }
 }
 
 /// Filter for entities with a specific component
@@ -226,17 +965,18 @@ impl QueryFilter for WithoutComponent {
     }
 }
 
-/// Combined filter that requires all sub-filters to match
+/// Combined filter that requires all listed component types
+///
+/// Use [`And`] to combine arbitrary filters.
 pub struct AllFilters {
     filters: Vec<Symbol>, // Simplified to just store component types
 }
 
 impl AllFilters {
     /// Create a new combined filter
-    pub fn new() -> Self {
-        let env = soroban_sdk::Env::default();
+    pub fn new(env: &Env) -> Self {
         Self {
-            filters: Vec::new(&env),
+            filters: Vec::new(env),
         }
     }
 
@@ -249,31 +989,29 @@ impl AllFilters {
 
 impl QueryFilter for AllFilters {
     fn matches(&self, world: &World, entity_id: EntityId) -> bool {
-        if let Some(entity) = world.get_entity(entity_id) {
-            for i in 0..self.filters.len() {
-                let ctype = self.filters.get(i).unwrap();
-                if !entity.has_component(&ctype) {
-                    return false;
-                }
-            }
-            true
-        } else {
-            false
+        let mask = match world.components.mask_of(&self.filters) {
+            Some(mask) => mask,
+            None => return false,
+        };
+        match world.get_entity(entity_id) {
+            Some(entity) => entity.signature().contains_all(mask),
+            None => false,
         }
     }
 }
 
-/// Combined filter that requires any sub-filter to match
+/// Combined filter that requires any listed component type
+///
+/// Use [`Or`] to combine arbitrary filters.
 pub struct AnyFilter {
     filters: Vec<Symbol>, // Simplified to just store component types
 }
 
 impl AnyFilter {
     /// Create a new combined filter
-    pub fn new() -> Self {
-        let env = soroban_sdk::Env::default();
+    pub fn new(env: &Env) -> Self {
         Self {
-            filters: Vec::new(&env),
+            filters: Vec::new(env),
         }
     }
 
@@ -286,24 +1024,22 @@ impl AnyFilter {
 
 impl QueryFilter for AnyFilter {
     fn matches(&self, world: &World, entity_id: EntityId) -> bool {
-        if let Some(entity) = world.get_entity(entity_id) {
-            for i in 0..self.filters.len() {
-                let ctype = self.filters.get(i).unwrap();
-                if entity.has_component(&ctype) {
-                    return true;
-                }
+        let mut mask = ComponentMask::new();
+        for ctype in self.filters.iter() {
+            if let Some(bit) = world.components.bit_index(&ctype) {
+                mask = mask.with(bit);
             }
-            false
-        } else {
-            false
+        }
+        match world.get_entity(entity_id) {
+            Some(entity) => entity.signature().intersects(mask),
+            None => false,
         }
     }
 }
 
 /// Execute a query with a custom filter
 pub fn query_with_filter(world: &World, filter: &dyn QueryFilter) -> Vec<EntityId> {
-    let env = soroban_sdk::Env::default();
-    let mut results = Vec::new(&env);
+    let mut results = Vec::new(world.env());
 
     for entity in world.iter_entities() {
         if filter.matches(world, entity.id()) {
@@ -317,17 +1053,20 @@ pub fn query_with_filter(world: &World, filter: &dyn QueryFilter) -> Vec<EntityI
 #[cfg(test)]
 mod tests {
     use super::*;
-    use soroban_sdk::{symbol_short, Env};
+    use crate::component::{Position, Velocity};
+    use soroban_sdk::{contracttype, symbol_short, Env};
 
     #[test]
     fn test_query_creation() {
-        let query = Query::new();
+        let env = Env::default();
+        let query = Query::new(&env);
         assert!(query.is_empty());
     }
 
     #[test]
     fn test_query_with_component() {
-        let query = Query::new()
+        let env = Env::default();
+        let query = Query::new(&env)
             .with_component(symbol_short!("position"))
             .with_component(symbol_short!("velocity"));
 
@@ -337,7 +1076,8 @@ mod tests {
 
     #[test]
     fn test_query_builder() {
-        let query = QueryBuilder::new()
+        let env = Env::default();
+        let query = QueryBuilder::new(&env)
             .with_component(symbol_short!("position"))
             .without_component(symbol_short!("dead"))
             .build();
@@ -349,10 +1089,11 @@ mod tests {
 
     #[test]
     fn test_query_state() {
-        let query = Query::new().with_component(symbol_short!("position"));
+        let env = Env::default();
+        let query = Query::new(&env).with_component(symbol_short!("position"));
         let mut query_state = QueryState::new(query);
 
-        let world = World::new();
+        let world = World::new(&env);
         let results = query_state.execute(&world);
         assert_eq!(results.len(), 0);
         assert!(query_state.is_empty());
@@ -360,8 +1101,9 @@ mod tests {
 
     #[test]
     fn test_with_component_filter() {
+        let env = Env::default();
         let filter = WithComponent::new(symbol_short!("position"));
-        let world = World::new();
+        let world = World::new(&env);
 
         // Since we have no entities with position components, this should return false
         let entity_id = EntityId::new(1, 0);
@@ -370,8 +1112,9 @@ mod tests {
 
     #[test]
     fn test_without_component_filter() {
+        let env = Env::default();
         let filter = WithoutComponent::new(symbol_short!("position"));
-        let world = World::new();
+        let world = World::new(&env);
 
         // Since we have no entities with position components, this should return true
         let entity_id = EntityId::new(1, 0);
@@ -380,11 +1123,12 @@ mod tests {
 
     #[test]
     fn test_all_filters() {
-        let filter = AllFilters::new()
+        let env = Env::default();
+        let filter = AllFilters::new(&env)
             .add_filter(symbol_short!("position"))
             .add_filter(symbol_short!("dead"));
 
-        let world = World::new();
+        let world = World::new(&env);
         let entity_id = EntityId::new(1, 0);
 
         // Should return false because no entity has position component
@@ -393,11 +1137,12 @@ mod tests {
 
     #[test]
     fn test_any_filter() {
-        let filter = AnyFilter::new()
+        let env = Env::default();
+        let filter = AnyFilter::new(&env)
             .add_filter(symbol_short!("position"))
             .add_filter(symbol_short!("velocity"));
 
-        let world = World::new();
+        let world = World::new(&env);
         let entity_id = EntityId::new(1, 0);
 
         // Should return false because no entity has either component
@@ -406,10 +1151,384 @@ mod tests {
 
     #[test]
     fn test_query_with_filter() {
+        let env = Env::default();
         let filter = WithComponent::new(symbol_short!("position"));
-        let world = World::new();
+        let world = World::new(&env);
 
         let results = query_with_filter(&world, &filter);
         assert_eq!(results.len(), 0);
     }
+
+    #[test]
+    fn test_query_compiles_to_masks() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let position = symbol_short!("position");
+        let velocity = symbol_short!("velocity");
+        let dead = symbol_short!("dead");
+        let data = soroban_sdk::Bytes::new(&env);
+
+        let moving = world.spawn_empty().id();
+        world.add_component_to_entity(moving, Component::new(position.clone(), data.clone()));
+        world.add_component_to_entity(moving, Component::new(velocity.clone(), data.clone()));
+        let corpse = world.spawn_empty().id();
+        world.add_component_to_entity(corpse, Component::new(position.clone(), data.clone()));
+        world.add_component_to_entity(corpse, Component::new(dead.clone(), data));
+
+        let query = Query::new(&env)
+            .with_component(position.clone())
+            .without_component(dead.clone())
+            .without_component(symbol_short!("unknown"));
+        let mask = query.compile(&world.components).unwrap();
+        assert_eq!(mask.required.count(), 1);
+        assert_eq!(mask.excluded.count(), 1);
+        assert!(mask.matches(world.get_entity(moving).unwrap().signature()));
+        assert!(!mask.matches(world.get_entity(corpse).unwrap().signature()));
+
+        let results = query.execute(&world);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results.get(0).unwrap(), moving);
+
+        // A required type nobody registered matches nothing
+        let query = Query::new(&env).with_component(symbol_short!("unknown"));
+        assert!(query.compile(&world.components).is_none());
+        assert!(query.execute(&world).is_empty());
+
+        let any = AnyFilter::new(&env).add_filter(velocity).add_filter(dead);
+        assert_eq!(query_with_filter(&world, &any).len(), 2);
+    }
+
+    #[test]
+    fn test_typed_queries() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let moving = world
+            .spawn_with((Position::new(0, 0), Velocity::new(1, 2)))
+            .id();
+        let frozen = world
+            .spawn_with((Position::new(5, 5), Velocity::new(3, 3)))
+            .id();
+        world.spawn_with(Position::new(9, 9));
+        let frozen_tag = Component::new(symbol_short!("frozen"), soroban_sdk::Bytes::new(&env));
+        world.add_component_to_entity(frozen, frozen_tag);
+
+        assert_eq!(world.query::<Position>().count(), 3);
+        let pairs: StdVec<_> = world.query::<(EntityId, Position, Velocity)>().collect();
+        assert_eq!(pairs.len(), 2);
+
+        // Builder filters still apply
+        let active = QueryBuilder::new(&env)
+            .without_component(symbol_short!("frozen"))
+            .build();
+        {
+            let mut query = active.iter_mut::<(Mut<Position>, Velocity)>(&mut world);
+            assert_eq!(query.len(), 1);
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+                position.y += velocity.y;
+            }
+        }
+        let position = world.get::<Position>(moving).unwrap();
+        assert_eq!((position.x, position.y), (1, 2));
+        assert_eq!(world.get::<Position>(frozen).unwrap().x, 5);
+
+        for (position, _) in world.query_mut::<(Mut<Position>, Velocity)>().iter_mut() {
+            position.x = 0;
+        }
+        assert!(world.query::<Position>().all(|p| p.x == 0 || p.x == 9));
+    }
+
+    #[test]
+    fn test_filter_combinators() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let data = soroban_sdk::Bytes::new(&env);
+        let tag = |world: &mut World, entity_id, name| {
+            world.add_component_to_entity(entity_id, Component::new(name, data.clone()));
+        };
+        let enemy = symbol_short!("enemy");
+        let frozen = symbol_short!("frozen");
+        let stunned = symbol_short!("stunned");
+        let boss = symbol_short!("boss");
+
+        let grunt = world.spawn_empty().id();
+        tag(&mut world, grunt, enemy.clone());
+        tag(&mut world, grunt, frozen.clone());
+        let dazed = world.spawn_empty().id();
+        tag(&mut world, dazed, enemy.clone());
+        tag(&mut world, dazed, stunned.clone());
+        tag(&mut world, dazed, frozen.clone());
+        let frozen_boss = world.spawn_empty().id();
+        tag(&mut world, frozen_boss, enemy.clone());
+        tag(&mut world, frozen_boss, frozen.clone());
+        tag(&mut world, frozen_boss, boss.clone());
+        let idle = world.spawn_empty().id();
+        tag(&mut world, idle, enemy.clone());
+        let frozen_ally = world.spawn_empty().id();
+        tag(&mut world, frozen_ally, frozen.clone());
+
+        let disabled = WithComponent::new(frozen.clone()).or(WithComponent::new(stunned.clone()));
+        let query = QueryBuilder::new(&env)
+            .with_component(enemy.clone())
+            .filter(disabled.and(Not::new(WithComponent::new(boss.clone()))))
+            .build();
+        let results = query.execute(&world);
+        assert_eq!(results.len(), 2);
+        assert!(results.contains(grunt));
+        assert!(results.contains(dazed));
+
+        let either = WithComponent::new(frozen.clone()).xor(WithComponent::new(stunned.clone()));
+        let results = query_with_filter(&world, &either);
+        assert_eq!(results.len(), 3);
+        assert!(!results.contains(dazed));
+
+        // Filters chosen at runtime nest as boxed trait objects
+        let mut tree: Box<dyn QueryFilter> = Box::new(WithComponent::new(enemy));
+        for excluded in [frozen, boss] {
+            tree = Box::new(And::new(tree, Not::new(WithComponent::new(excluded))));
+        }
+        let results = query_with_filter(&world, &tree);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results.get(0).unwrap(), idle);
+    }
+
+    #[test]
+    fn test_predicate_queries() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let dead = symbol_short!("dead");
+        let mut spawned = StdVec::new();
+        for x in [7, -2, 4, 12, 0] {
+            spawned.push(world.spawn_with(Position::new(x, 0)).id());
+        }
+        let corpse = world.spawn_with(Position::new(1, 0)).id();
+        let tag = Component::new(dead.clone(), soroban_sdk::Bytes::new(&env));
+        world.add_component_to_entity(corpse, tag);
+
+        let near = QueryBuilder::new(&env)
+            .where_component(|p: &Position| p.x.abs() <= 4)
+            .without_component(dead.clone())
+            .build();
+        assert_eq!(near.execute(&world).len(), 3);
+
+        let xs = |query: &Query| -> StdVec<i32> {
+            query.iter::<Position>(&world).map(|p| p.x).collect()
+        };
+        let sorted = QueryBuilder::new(&env)
+            .without_component(dead)
+            .order_by(|p: &Position| p.x)
+            .build();
+        assert_eq!(xs(&sorted), [-2, 0, 4, 7, 12]);
+        assert_eq!(xs(&sorted.clone().offset(1).limit(2)), [0, 4]);
+        assert_eq!(xs(&sorted.clone().offset(4).limit(3)), [12]);
+
+        let descending = Query::new(&env)
+            .order_by(|p: &Position| core::cmp::Reverse(p.x))
+            .limit(2);
+        assert_eq!(xs(&descending), [12, 7]);
+
+        // Unsorted queries page in archetype order
+        let paged = Query::new(&env)
+            .with_component(symbol_short!("position"))
+            .limit(4);
+        assert_eq!(paged.execute(&world).len(), 4);
+        assert_eq!(paged.offset(5).execute(&world).len(), 1);
+    }
+
+    #[contracttype]
+    #[derive(Clone, cougr_derive::Component)]
+    #[component(name = "team")]
+    struct Team {
+        id: u32,
+    }
+
+    #[contracttype]
+    #[derive(Clone, cougr_derive::Component)]
+    #[component(name = "gold")]
+    struct Gold {
+        amount: i128,
+    }
+
+    #[test]
+    fn test_aggregations() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        for (team, amount, x) in [(1, 50, 3), (2, 20, 8), (1, 30, 1), (2, 5, 6)] {
+            world.spawn_with((Team { id: team }, Gold { amount }, Position::new(x, 0)));
+        }
+        let neutral = world.spawn_with(Gold { amount: 1000 }).id();
+
+        let members = Query::new(&env).with_component(symbol_short!("team"));
+        assert_eq!(members.count(&world), 4);
+        assert_eq!(members.sum_by(&world, |gold: &Gold| gold.amount), 105);
+        assert_eq!(
+            Query::new(&env).sum_by(&world, |gold: &Gold| gold.amount),
+            1105
+        );
+
+        let (poorest, gold) = members
+            .min_by_key(&world, |gold: &Gold| gold.amount)
+            .unwrap();
+        assert_eq!(gold.amount, 5);
+        assert_eq!(world.get::<Position>(poorest).unwrap().x, 6);
+        let (richest, _) = Query::new(&env)
+            .max_by_key(&world, |gold: &Gold| gold.amount)
+            .unwrap();
+        assert_eq!(richest, neutral);
+
+        let sizes = members.group_by::<Team, _>(&world, |team| team.id);
+        assert_eq!(sizes.get(&1), Some(&2));
+        assert_eq!(sizes.get(&2), Some(&2));
+        let totals = members.group_sum_by(&world, |team: &Team| team.id, |gold: &Gold| gold.amount);
+        assert_eq!(totals.get(&1), Some(&80));
+        assert_eq!(totals.get(&2), Some(&25));
+
+        // Filters and paging apply before aggregating
+        let rich = members
+            .clone()
+            .where_component(|gold: &Gold| gold.amount >= 20)
+            .order_by(|position: &Position| position.x)
+            .limit(2);
+        assert_eq!(rich.count(&world), 2);
+        assert_eq!(rich.sum_by(&world, |gold: &Gold| gold.amount), 80);
+
+        let mut state = QueryState::new(rich);
+        state.execute(&world);
+        assert_eq!(state.sum_by(&world, |gold: &Gold| gold.amount), 80);
+        assert_eq!(
+            state
+                .group_sum_by(&world, |team: &Team| team.id, |gold: &Gold| gold.amount)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_cached_query_state() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let dead = symbol_short!("dead");
+        let tag = Component::new(dead.clone(), soroban_sdk::Bytes::new(&env));
+        let a = world
+            .spawn_with((Position::new(0, 0), Velocity::new(1, 1)))
+            .id();
+        let b = world.spawn_with(Position::new(1, 0)).id();
+        world.add_component_to_entity(b, tag.clone());
+        world.spawn_with(Velocity::new(2, 2));
+
+        let mut state = QueryBuilder::new(&env)
+            .with_component(symbol_short!("position"))
+            .without_component(dead.clone())
+            .build_state();
+        assert!(state.needs_update(&world));
+        assert_eq!(state.execute(&world).len(), 1);
+        assert_eq!(state.last_version(), Some(world.changes.version()));
+        assert!(!state.needs_update(&world));
+
+        // Changes to unrelated component types keep the cache
+        world.insert(a, Velocity::new(5, 5));
+        world.spawn_with(Velocity::new(3, 3));
+        assert!(!state.needs_update(&world));
+
+        // A few moved entities patch the cached results
+        world.remove_component_from_entity(b, &dead);
+        let c = world.spawn_with(Position::new(2, 0)).id();
+        world.add_component_to_entity(a, tag);
+        assert!(state.needs_update(&world));
+        let results = state.execute(&world).clone();
+        assert_eq!(results.len(), 2);
+        assert!(results.contains(b) && results.contains(c));
+        assert!(!state.needs_update(&world));
+
+        world.despawn(c);
+        assert_eq!(state.execute(&world).len(), 1);
+
+        // Falling behind the change log runs the query again
+        for x in 0..crate::change::CHANGE_LOG_CAPACITY as i32 {
+            world.spawn_with(Position::new(x, x));
+        }
+        let fresh = state.query.execute(&world);
+        assert_eq!(state.execute(&world), &fresh);
+
+        // Queries without required types track spawns and despawns
+        let mut alive = QueryBuilder::new(&env)
+            .without_component(dead)
+            .build_state();
+        let count = alive.execute(&world).len();
+        world.spawn_empty();
+        assert!(alive.needs_update(&world));
+        assert_eq!(alive.execute(&world).len(), count + 1);
+
+        world.clear_entities();
+        assert!(state.needs_update(&world));
+        assert!(state.execute(&world).is_empty());
+    }
+
+    #[test]
+    fn test_cached_state_sees_in_place_writes() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let position = symbol_short!("position");
+        let entity = world.spawn_with(Position::new(1, 0)).id();
+
+        let query = QueryBuilder::new(&env)
+            .where_component(|p: &Position| p.x < 5)
+            .build();
+        let mut state = QueryState::new(query.clone());
+        assert_eq!(state.execute(&world).len(), 1);
+
+        world.modify_component(entity, &position, |data| {
+            *data = Position::new(100, 0).serialize(&env);
+        });
+        assert_eq!(query.execute(&world).len(), 0);
+        assert_eq!(state.execute(&world).len(), 0);
+
+        // Writes through the guard are seen as well
+        if let Some(mut component) = world.get_component_mut(entity, &position) {
+            *component.data_mut() = Position::new(2, 0).serialize(&env);
+        }
+        assert_eq!(state.execute(&world).len(), 1);
+
+        // Guards dropped without a change record nothing
+        let version = world.changes.version();
+        world.get_component_mut(entity, &position).unwrap();
+        world.modify_component(entity, &position, |_| {});
+        world.query_mut::<Mut<Position>>();
+        assert_eq!(world.changes.version(), version);
+        assert!(!state.needs_update(&world));
+    }
+
+    #[test]
+    fn test_patched_results_keep_query_order() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let dead = symbol_short!("dead");
+        let ids: StdVec<EntityId> = (0..4)
+            .map(|x| world.spawn_with(Position::new(x, 0)).id())
+            .collect();
+        let query = QueryBuilder::new(&env)
+            .where_component(|p: &Position| p.x < 5)
+            .without_component(dead.clone())
+            .build();
+        let mut state = QueryState::new(query.clone());
+        assert_eq!(state.execute(&world).len(), 4);
+
+        // Leaving and rejoining the results keeps the entity's place
+        world.insert(ids[1], Position::new(9, 0));
+        assert_eq!(state.execute(&world), &query.execute(&world));
+        world.insert(ids[1], Position::new(1, 0));
+        world.insert(ids[0], Position::new(9, 0));
+        let patched = state.execute(&world).clone();
+        assert_eq!(patched, query.execute(&world));
+        assert_eq!(patched.get(0), Some(ids[1]));
+
+        // Entities moving between archetypes follow the archetype order
+        let tag = Component::new(dead.clone(), soroban_sdk::Bytes::new(&env));
+        world.add_component_to_entity(ids[2], tag);
+        world.remove_component_from_entity(ids[2], &dead);
+        world.insert(ids[0], Position::new(0, 0));
+        let patched = state.execute(&world).clone();
+        assert_eq!(patched.len(), 4);
+        assert_eq!(patched, query.execute(&world));
+    }
 }
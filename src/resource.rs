@@ -1,4 +1,5 @@
-use soroban_sdk::{contracttype, symbol_short, Bytes, Env, Symbol};
+use core::ops::{Deref, DerefMut};
+use soroban_sdk::{contracttype, Bytes, Env, Map, Symbol};
 
 #[contracttype]
 #[derive(Debug, Clone)]
@@ -13,6 +14,10 @@ impl Resource {
             data,
         }
     }
+    /// Build a resource from a typed value
+    pub fn from_typed<R: ResourceTrait>(env: &Env, value: &R) -> Self {
+        Self::new(R::resource_type(), value.serialize(env))
+    }
     pub fn resource_type(&self) -> &Symbol {
         &self.resource_type
     }
@@ -24,7 +29,82 @@ impl Resource {
     }
 }
 
+/// Mutable handle to a resource stored in a `World`
+///
+/// The resource is written back into the world's resources on drop.
+pub struct ResMut<'a> {
+    resources: &'a mut Map<Symbol, Resource>,
+    resource: Resource,
+}
+
+impl<'a> ResMut<'a> {
+    pub(crate) fn new(resources: &'a mut Map<Symbol, Resource>, resource: Resource) -> Self {
+        Self {
+            resources,
+            resource,
+        }
+    }
+}
+
+impl Deref for ResMut<'_> {
+    type Target = Resource;
+
+    fn deref(&self) -> &Self::Target {
+        &self.resource
+    }
+}
+
+impl DerefMut for ResMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.resource
+    }
+}
+
+impl Drop for ResMut<'_> {
+    fn drop(&mut self) {
+        self.resources
+            .set(self.resource.resource_type().clone(), self.resource.clone());
+    }
+}
+
+/// Mutable handle to a typed resource stored in a `World`
+///
+/// Dereferences to the decoded value, which is re-encoded and written back
+/// into the world's resources on drop.
+pub struct ResourceMut<'a, R: ResourceTrait> {
+    resources: &'a mut Map<Symbol, Resource>,
+    value: R,
+}
+
+impl<'a, R: ResourceTrait> ResourceMut<'a, R> {
+    pub(crate) fn new(resources: &'a mut Map<Symbol, Resource>, value: R) -> Self {
+        Self { resources, value }
+    }
+}
+
+impl<R: ResourceTrait> Deref for ResourceMut<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<R: ResourceTrait> DerefMut for ResourceMut<'_, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+impl<R: ResourceTrait> Drop for ResourceMut<'_, R> {
+    fn drop(&mut self) {
+        let resource = Resource::from_typed(self.resources.env(), &self.value);
+        self.resources.set(R::resource_type(), resource);
+    }
+}
+
 pub trait ResourceTrait: Send + Sync + 'static {
+    /// Name of this resource type; a world holds at most one resource per name
     fn resource_type() -> Symbol;
     fn serialize(&self, env: &Env) -> Bytes;
     fn deserialize(env: &Env, data: &Bytes) -> Option<Self>
@@ -33,7 +113,8 @@ pub trait ResourceTrait: Send + Sync + 'static {
 }
 
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, cougr_derive::Resource)]
+#[resource(name = "gamestate")]
 pub struct GameState {
     pub score: i32,
     pub level: i32,
@@ -57,44 +138,6 @@ impl GameState {
         self.is_game_over = true;
     }
 }
-impl ResourceTrait for GameState {
-    fn resource_type() -> Symbol {
-        symbol_short!("gamestate")
-    }
-    fn serialize(&self, env: &Env) -> Bytes {
-        let mut bytes = Bytes::new(env);
-        let score_bytes = Bytes::from_array(env, &self.score.to_be_bytes());
-        let level_bytes = Bytes::from_array(env, &self.level.to_be_bytes());
-        let game_over_bytes = Bytes::from_array(env, &[self.is_game_over as u8]);
-        bytes.append(&score_bytes);
-        bytes.append(&level_bytes);
-        bytes.append(&game_over_bytes);
-        bytes
-    }
-    fn deserialize(env: &Env, data: &Bytes) -> Option<Self> {
-        if data.len() != 9 {
-            return None;
-        }
-        let score = i32::from_be_bytes([
-            data.get(0).unwrap(),
-            data.get(1).unwrap(),
-            data.get(2).unwrap(),
-            data.get(3).unwrap(),
-        ]);
-        let level = i32::from_be_bytes([
-            data.get(4).unwrap(),
-            data.get(5).unwrap(),
-            data.get(6).unwrap(),
-            data.get(7).unwrap(),
-        ]);
-        let is_game_over = data.get(8).unwrap() != 0;
-        Some(Self {
-            score,
-            level,
-            is_game_over,
-        })
-    }
-}
 impl Default for GameState {
     fn default() -> Self {
         Self::new()
@@ -104,7 +147,7 @@ impl Default for GameState {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use soroban_sdk::Env;
+    use soroban_sdk::{symbol_short, Env};
 
     #[test]
     fn test_resource_creation() {
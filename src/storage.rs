@@ -1,121 +1,1550 @@
-use crate::component::Component;
+use crate::change::ChangeTracker;
+use crate::component::{
+    Bundle, Component, ComponentId, ComponentMask, ComponentRegistry, ComponentStorage,
+    ComponentTrait, Durability, Migration, MigrationRegistry,
+};
 use crate::entity::EntityId;
-use soroban_sdk::{contracttype, Bytes, Env, Symbol, Vec};
+use core::ops::{Deref, DerefMut};
+use soroban_sdk::{contracttype, Bytes, Env, IntoVal, Map, Symbol, TryFromVal, Val, Vec};
 
+/// Dense column store for components most entities have
+///
+/// Each component owns a packed column of `(entity, data, version)` rows;
+/// removal swaps the last row into the hole so columns stay dense and can be
+/// walked without lookups.
+#[contracttype]
+#[derive(Debug, Clone)]
+pub struct TableStorage {
+    /// Column of each component, by component ID
+    pub columns: Map<u32, Column>,
+    /// Row of each `(id, generation, component ID)` within its column
+    pub rows: Map<(u64, u32, u32), u32>,
+}
+
+/// Packed rows of one component in a [`TableStorage`]
+#[contracttype]
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub entities: Vec<(u64, u32)>,
+    pub data: Vec<Bytes>,
+    pub versions: Vec<u32>,
+}
+
+impl TableStorage {
+    pub fn new(env: &Env) -> Self {
+        Self {
+            columns: Map::new(env),
+            rows: Map::new(env),
+        }
+    }
+
+    /// Insert or replace a component's data and schema version
+    pub fn insert(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        data: Bytes,
+        version: u32,
+    ) {
+        let key = component_key(entity_id, component_id);
+        let mut column = self.column(component_id);
+        match self.rows.get(key) {
+            Some(row) => {
+                column.data.set(row, data);
+                column.versions.set(row, version);
+            }
+            None => {
+                self.rows.set(key, column.entities.len());
+                column.entities.push_back(entity_key(entity_id));
+                column.data.push_back(data);
+                column.versions.push_back(version);
+            }
+        }
+        self.columns.set(component_id.id(), column);
+    }
+
+    /// Get a component's data and schema version
+    pub fn get(&self, entity_id: EntityId, component_id: ComponentId) -> Option<(Bytes, u32)> {
+        let row = self.rows.get(component_key(entity_id, component_id))?;
+        let column = self.columns.get(component_id.id())?;
+        Some((column.data.get(row)?, column.versions.get(row)?))
+    }
+
+    /// Remove a component, swapping the column's last row into its place
+    pub fn remove(&mut self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        let key = component_key(entity_id, component_id);
+        let row = match self.rows.get(key) {
+            Some(row) => row,
+            None => return false,
+        };
+        self.rows.remove(key);
+
+        let mut column = self.column(component_id);
+        let last_entity = column.entities.pop_back().unwrap();
+        let last_data = column.data.pop_back().unwrap();
+        let last_version = column.versions.pop_back().unwrap();
+        if row < column.entities.len() {
+            column.entities.set(row, last_entity);
+            column.data.set(row, last_data);
+            column.versions.set(row, last_version);
+            let (id, generation) = last_entity;
+            self.rows.set((id, generation, component_id.id()), row);
+        }
+        if column.entities.is_empty() {
+            self.columns.remove(component_id.id());
+        } else {
+            self.columns.set(component_id.id(), column);
+        }
+        true
+    }
+
+    /// Check if a component is present
+    pub fn contains(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.rows
+            .contains_key(component_key(entity_id, component_id))
+    }
+
+    /// Get the entities that have a component, in column order
+    pub fn entities(&self, component_id: ComponentId) -> Vec<EntityId> {
+        let mut entities = Vec::new(self.rows.env());
+        if let Some(column) = self.columns.get(component_id.id()) {
+            for (id, generation) in column.entities.iter() {
+                entities.push_back(EntityId::new(id, generation));
+            }
+        }
+        entities
+    }
+
+    /// Upgrade every row stored under an outdated schema version
+    pub fn migrate_all(&mut self, names: &Map<u32, Symbol>, migrations: &MigrationRegistry) -> u32 {
+        let mut migrated = 0;
+        for (component_id, mut column) in self.columns.iter() {
+            let component_type = match names.get(component_id) {
+                Some(component_type) => component_type,
+                None => continue,
+            };
+            let mut changed = false;
+            for row in 0..column.entities.len() {
+                let mut component =
+                    Component::new(component_type.clone(), column.data.get(row).unwrap());
+                component.set_version(column.versions.get(row).unwrap());
+                if migrations.migrate(&mut component) {
+                    column.data.set(row, component.data().clone());
+                    column.versions.set(row, component.version());
+                    changed = true;
+                    migrated += 1;
+                }
+            }
+            if changed {
+                self.columns.set(component_id, column);
+            }
+        }
+        migrated
+    }
+
+    pub fn len(&self) -> u32 {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn column(&self, component_id: ComponentId) -> Column {
+        self.columns
+            .get(component_id.id())
+            .unwrap_or_else(|| Column {
+                entities: Vec::new(self.rows.env()),
+                data: Vec::new(self.rows.env()),
+                versions: Vec::new(self.rows.env()),
+            })
+    }
+}
+
+/// Sparse store for components few entities have
+///
+/// Each component owns a sparse set: a packed list of the entities holding it,
+/// and an index from entity to its place in that list. Listing the holders of
+/// a rare tag touches only that tag's set, and removal swaps the last entry
+/// into the hole like a table column.
+#[contracttype]
+#[derive(Debug, Clone)]
+pub struct SparseStorage {
+    /// Sparse set of each component, by component ID
+    pub sets: Map<u32, SparseSet>,
+}
+
+/// Entities holding one component in a [`SparseStorage`]
+#[contracttype]
+#[derive(Debug, Clone)]
+pub struct SparseSet {
+    /// Packed `(id, generation)` of each entity holding the component
+    pub dense: Vec<(u64, u32)>,
+    /// Data of each entity, parallel to `dense`
+    pub data: Vec<Bytes>,
+    /// Position of each `(id, generation)` entity in `dense`
+    pub sparse: Map<(u64, u32), u32>,
+    /// Schema versions of entries encoded with a version above 0
+    pub versions: Map<(u64, u32), u32>,
+}
+
+impl SparseStorage {
+    pub fn new(env: &Env) -> Self {
+        Self {
+            sets: Map::new(env),
+        }
+    }
+
+    /// Insert or replace a component's data and schema version
+    pub fn insert(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        data: Bytes,
+        version: u32,
+    ) {
+        let key = entity_key(entity_id);
+        let mut set = self.set(component_id);
+        match set.sparse.get(key) {
+            Some(index) => set.data.set(index, data),
+            None => {
+                set.sparse.set(key, set.dense.len());
+                set.dense.push_back(key);
+                set.data.push_back(data);
+            }
+        }
+        if version == 0 {
+            set.versions.remove(key);
+        } else {
+            set.versions.set(key, version);
+        }
+        self.sets.set(component_id.id(), set);
+    }
+
+    /// Get a component's data and schema version
+    pub fn get(&self, entity_id: EntityId, component_id: ComponentId) -> Option<(Bytes, u32)> {
+        let key = entity_key(entity_id);
+        let set = self.sets.get(component_id.id())?;
+        let data = set.data.get(set.sparse.get(key)?)?;
+        Some((data, set.versions.get(key).unwrap_or(0)))
+    }
+
+    /// Remove a component, swapping the set's last entry into its place
+    pub fn remove(&mut self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        let key = entity_key(entity_id);
+        let mut set = match self.sets.get(component_id.id()) {
+            Some(set) => set,
+            None => return false,
+        };
+        let index = match set.sparse.get(key) {
+            Some(index) => index,
+            None => return false,
+        };
+        set.sparse.remove(key);
+        set.versions.remove(key);
+
+        let last_entity = set.dense.pop_back().unwrap();
+        let last_data = set.data.pop_back().unwrap();
+        if index < set.dense.len() {
+            set.dense.set(index, last_entity);
+            set.data.set(index, last_data);
+            set.sparse.set(last_entity, index);
+        }
+        if set.dense.is_empty() {
+            self.sets.remove(component_id.id());
+        } else {
+            self.sets.set(component_id.id(), set);
+        }
+        true
+    }
+
+    /// Check if a component is present
+    pub fn contains(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.sets
+            .get(component_id.id())
+            .is_some_and(|set| set.sparse.contains_key(entity_key(entity_id)))
+    }
+
+    /// Get the entities that have a component, in set order
+    pub fn entities(&self, component_id: ComponentId) -> Vec<EntityId> {
+        let mut entities = Vec::new(self.sets.env());
+        if let Some(set) = self.sets.get(component_id.id()) {
+            for (id, generation) in set.dense.iter() {
+                entities.push_back(EntityId::new(id, generation));
+            }
+        }
+        entities
+    }
+
+    /// Upgrade every entry stored under an outdated schema version
+    pub fn migrate_all(&mut self, names: &Map<u32, Symbol>, migrations: &MigrationRegistry) -> u32 {
+        let mut migrated = 0;
+        for (component_id, mut set) in self.sets.iter() {
+            let component_type = match names.get(component_id) {
+                Some(component_type) => component_type,
+                None => continue,
+            };
+            let mut changed = false;
+            for index in 0..set.dense.len() {
+                let key = set.dense.get(index).unwrap();
+                let mut component =
+                    Component::new(component_type.clone(), set.data.get(index).unwrap());
+                component.set_version(set.versions.get(key).unwrap_or(0));
+                if migrations.migrate(&mut component) {
+                    set.data.set(index, component.data().clone());
+                    match component.version() {
+                        0 => {
+                            set.versions.remove(key);
+                        }
+                        version => set.versions.set(key, version),
+                    }
+                    changed = true;
+                    migrated += 1;
+                }
+            }
+            if changed {
+                self.sets.set(component_id, set);
+            }
+        }
+        migrated
+    }
+
+    pub fn len(&self) -> u32 {
+        self.sets.values().iter().map(|set| set.dense.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    fn set(&self, component_id: ComponentId) -> SparseSet {
+        self.sets
+            .get(component_id.id())
+            .unwrap_or_else(|| SparseSet {
+                dense: Vec::new(self.sets.env()),
+                data: Vec::new(self.sets.env()),
+                sparse: Map::new(self.sets.env()),
+                versions: Map::new(self.sets.env()),
+            })
+    }
+}
+
+/// Component storage routing each component to its table or sparse backend
+///
+/// Entries are keyed by [`ComponentId`]; each component's name is kept once,
+/// not per entry. The backend is fixed by the [`ComponentStorage`] kind a
+/// component is first stored with. A per-entity index lists the components
+/// attached to each entity.
 #[contracttype]
 #[derive(Debug, Clone)]
 pub struct Storage {
-    pub entity_ids: Vec<u64>,
-    pub component_types: Vec<Symbol>,
-    pub component_data: Vec<Bytes>,
+    pub table: TableStorage,
+    pub sparse: SparseStorage,
+    /// Backend kind of each component ID
+    pub kinds: Map<u32, ComponentStorage>,
+    /// Name of each component ID
+    pub names: Map<u32, Symbol>,
+    /// Component IDs of each `(id, generation)` entity
+    pub entity_index: Map<(u64, u32), Vec<u32>>,
 }
 
 impl Storage {
-    pub fn new() -> Self {
-        let env = soroban_sdk::Env::default();
+    pub fn new(env: &Env) -> Self {
         Self {
-            entity_ids: Vec::new(&env),
-            component_types: Vec::new(&env),
-            component_data: Vec::new(&env),
+            table: TableStorage::new(env),
+            sparse: SparseStorage::new(env),
+            kinds: Map::new(env),
+            names: Map::new(env),
+            entity_index: Map::new(env),
         }
     }
 
-    /// Add a component to storage
-    pub fn add_component(&mut self, entity_id: EntityId, component: Component) {
-        self.remove_component(entity_id, component.component_type().clone());
-        self.entity_ids.push_back(entity_id.id());
-        self.component_types
-            .push_back(component.component_type().clone());
-        self.component_data.push_back(component.data().clone());
+    /// The environment this storage's collections live on
+    pub fn env(&self) -> &Env {
+        self.kinds.env()
+    }
+
+    /// Get the backend kind a component is stored in
+    pub fn kind(&self, component_id: ComponentId) -> Option<ComponentStorage> {
+        self.kinds.get(component_id.id())
+    }
+
+    /// Add a component to storage, replacing any existing entry of the same type
+    pub fn add_component(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        component: Component,
+    ) {
+        if !self.has_component(entity_id, component_id) {
+            let mut ids = self
+                .entity_index
+                .get(entity_key(entity_id))
+                .unwrap_or_else(|| Vec::new(self.env()));
+            ids.push_back(component_id.id());
+            self.entity_index.set(entity_key(entity_id), ids);
+        }
+        self.write(entity_id, component_id, &component);
     }
 
     /// Remove a component from storage
-    pub fn remove_component(&mut self, entity_id: EntityId, component_type: Symbol) -> bool {
-        let mut found = false;
-        let mut new_entity_ids = Vec::new(&soroban_sdk::Env::default());
-        let mut new_component_types = Vec::new(&soroban_sdk::Env::default());
-        let mut new_component_data = Vec::new(&soroban_sdk::Env::default());
-        for i in 0..self.entity_ids.len() {
-            let eid = self.entity_ids.get(i).unwrap();
-            let ctype = self.component_types.get(i).unwrap();
-            let cdata = self.component_data.get(i).unwrap();
-            if eid == entity_id.id() && ctype == component_type {
-                found = true;
-            } else {
-                new_entity_ids.push_back(eid);
-                new_component_types.push_back(ctype.clone());
-                new_component_data.push_back(cdata.clone());
-            }
-        }
-        if found {
-            self.entity_ids = new_entity_ids;
-            self.component_types = new_component_types;
-            self.component_data = new_component_data;
-        }
-        found
+    pub fn remove_component(&mut self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        let removed = match self.kind(component_id) {
+            Some(ComponentStorage::Table) => self.table.remove(entity_id, component_id),
+            Some(ComponentStorage::Sparse) => self.sparse.remove(entity_id, component_id),
+            None => false,
+        };
+        if !removed {
+            return false;
+        }
+
+        let mut ids = self.entity_index.get(entity_key(entity_id)).unwrap();
+        if let Some(index) = ids.first_index_of(component_id.id()) {
+            ids.remove(index);
+        }
+        if ids.is_empty() {
+            self.entity_index.remove(entity_key(entity_id));
+        } else {
+            self.entity_index.set(entity_key(entity_id), ids);
+        }
+        true
     }
 
-    /// Get a component from storage
-    pub fn get_component(&self, entity_id: EntityId, component_type: Symbol) -> Option<Component> {
-        for i in 0..self.entity_ids.len() {
-            let eid = self.entity_ids.get(i).unwrap();
-            let ctype = self.component_types.get(i).unwrap();
-            let cdata = self.component_data.get(i).unwrap();
-            if eid == entity_id.id() && ctype == component_type {
-                return Some(Component::new(ctype.clone(), cdata.clone()));
-            }
+    /// Remove every component of an entity
+    ///
+    /// Returns the number of components removed.
+    pub fn remove_entity(&mut self, entity_id: EntityId) -> u32 {
+        let ids = match self.entity_index.get(entity_key(entity_id)) {
+            Some(ids) => ids,
+            None => return 0,
+        };
+        for component_id in ids.iter() {
+            self.remove_component(entity_id, ComponentId::new(component_id));
         }
-        None
+        ids.len()
+    }
+
+    /// Get a component from storage
+    pub fn get_component(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> Option<Component> {
+        let kind = self.kind(component_id)?;
+        let (data, version) = match kind {
+            ComponentStorage::Table => self.table.get(entity_id, component_id)?,
+            ComponentStorage::Sparse => self.sparse.get(entity_id, component_id)?,
+        };
+        let component_type = self.names.get(component_id.id())?;
+        let mut component = Component::with_storage(component_type, data, kind);
+        component.set_version(version);
+        Some(component)
+    }
+
+    /// Get a mutable handle to a component in storage
+    ///
+    /// The component data is written back when the returned guard is dropped.
+    pub fn get_component_mut(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> Option<ComponentMut<'_>> {
+        let component = self.get_component(entity_id, component_id)?;
+        let stored = (component.data().clone(), component.version());
+        Some(ComponentMut {
+            storage: self,
+            entity_id,
+            component_id,
+            component,
+            stored,
+            changes: None,
+        })
     }
 
     /// Check if a component exists in storage
-    pub fn has_component(&self, entity_id: EntityId, component_type: Symbol) -> bool {
-        for i in 0..self.entity_ids.len() {
-            let eid = self.entity_ids.get(i).unwrap();
-            let ctype = self.component_types.get(i).unwrap();
-            if eid == entity_id.id() && ctype == component_type {
-                return true;
-            }
+    pub fn has_component(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        match self.kind(component_id) {
+            Some(ComponentStorage::Table) => self.table.contains(entity_id, component_id),
+            Some(ComponentStorage::Sparse) => self.sparse.contains(entity_id, component_id),
+            None => false,
         }
-        false
     }
 
     /// Get all components for an entity
     pub fn get_entity_components(&self, entity_id: EntityId) -> Vec<Component> {
-        let env = soroban_sdk::Env::default();
-        let mut components = Vec::new(&env);
-        for i in 0..self.entity_ids.len() {
-            let eid = self.entity_ids.get(i).unwrap();
-            let ctype = self.component_types.get(i).unwrap();
-            let cdata = self.component_data.get(i).unwrap();
-            if eid == entity_id.id() {
-                components.push_back(Component::new(ctype.clone(), cdata.clone()));
+        let mut components = Vec::new(self.env());
+        if let Some(ids) = self.entity_index.get(entity_key(entity_id)) {
+            for component_id in ids.iter() {
+                if let Some(component) =
+                    self.get_component(entity_id, ComponentId::new(component_id))
+                {
+                    components.push_back(component);
+                }
             }
         }
         components
     }
 
+    /// Get the entities that have a component
+    pub fn entities_with(&self, component_id: ComponentId) -> Vec<EntityId> {
+        match self.kind(component_id) {
+            Some(ComponentStorage::Table) => self.table.entities(component_id),
+            Some(ComponentStorage::Sparse) => self.sparse.entities(component_id),
+            None => Vec::new(self.env()),
+        }
+    }
+
     pub fn clear(&mut self) {
-        let env = soroban_sdk::Env::default();
-        self.entity_ids = Vec::new(&env);
-        self.component_types = Vec::new(&env);
-        self.component_data = Vec::new(&env);
+        let env = self.env().clone();
+        self.table = TableStorage::new(&env);
+        self.sparse = SparseStorage::new(&env);
+        self.entity_index = Map::new(&env);
+    }
+
+    /// Upgrade every entry stored under an outdated schema version
+    ///
+    /// Returns the number of entries that were rewritten.
+    pub fn migrate_all(&mut self, migrations: &MigrationRegistry) -> u32 {
+        self.table.migrate_all(&self.names, migrations)
+            + self.sparse.migrate_all(&self.names, migrations)
     }
 
     pub fn len(&self) -> usize {
-        self.entity_ids.len().try_into().unwrap()
+        (self.table.len() + self.sparse.len()).try_into().unwrap()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.entity_ids.is_empty()
+        self.table.is_empty() && self.sparse.is_empty()
+    }
+
+    /// Write component data into the backend its component is bound to
+    fn write(&mut self, entity_id: EntityId, component_id: ComponentId, component: &Component) {
+        let kind = match self.kind(component_id) {
+            Some(kind) => kind,
+            None => {
+                self.kinds.set(component_id.id(), component.storage());
+                self.names
+                    .set(component_id.id(), component.component_type().clone());
+                component.storage()
+            }
+        };
+        let data = component.data().clone();
+        match kind {
+            ComponentStorage::Table => {
+                self.table
+                    .insert(entity_id, component_id, data, component.version())
+            }
+            ComponentStorage::Sparse => {
+                self.sparse
+                    .insert(entity_id, component_id, data, component.version())
+            }
+        }
+    }
+}
+
+fn entity_key(entity_id: EntityId) -> (u64, u32) {
+    (entity_id.id(), entity_id.generation())
+}
+
+fn component_key(entity_id: EntityId, component_id: ComponentId) -> (u64, u32, u32) {
+    (entity_id.id(), entity_id.generation(), component_id.id())
+}
+
+/// Mutable handle to a component held in [`Storage`]
+///
+/// Dereferences to the component; if its data or schema version changed, it
+/// is persisted back into storage when the guard goes out of scope.
+pub struct ComponentMut<'a> {
+    storage: &'a mut Storage,
+    entity_id: EntityId,
+    component_id: ComponentId,
+    component: Component,
+    /// Data and schema version as stored
+    stored: (Bytes, u32),
+    /// Tracker to record the write in, for guards handed out by a `World`
+    changes: Option<&'a mut ChangeTracker>,
+}
+
+impl<'a> ComponentMut<'a> {
+    /// Record the write-back, if any, in `changes`
+    pub(crate) fn track(mut self, changes: &'a mut ChangeTracker) -> Self {
+        self.changes = Some(changes);
+        self
     }
 }
 
-impl Default for Storage {
-    fn default() -> Self {
-        Self::new()
+impl Deref for ComponentMut<'_> {
+    type Target = Component;
+
+    fn deref(&self) -> &Self::Target {
+        &self.component
+    }
+}
+
+impl DerefMut for ComponentMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.component
+    }
+}
+
+impl Drop for ComponentMut<'_> {
+    fn drop(&mut self) {
+        let (data, version) = &self.stored;
+        if self.component.data() == data && self.component.version() == *version {
+            return;
+        }
+        self.storage
+            .write(self.entity_id, self.component_id, &self.component);
+        if let Some(changes) = self.changes.as_mut() {
+            changes.record(
+                self.entity_id,
+                ComponentMask::new().with(self.component_id.bit()),
+            );
+        }
+    }
+}
+
+/// Ledger key of a [`PersistentWorld`] entry
+#[contracttype]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorldKey {
+    /// The entity allocator
+    Allocator,
+    /// Component names and durabilities, ordered by component ID
+    Registry,
+    /// The record of one entity slot, by ID
+    Entity(u64),
+    /// One component of one entity: `(entity ID, component ID)`
+    Component(u64, u32),
+}
+
+/// Next fresh entity ID and the IDs free for reuse
+#[contracttype]
+#[derive(Debug, Clone)]
+pub struct EntityAllocator {
+    pub next_id: u64,
+    pub free_list: Vec<u64>,
+}
+
+/// Ledger record of an entity slot
+///
+/// Despawned slots keep their record so the bumped generation survives until
+/// the ID is reused.
+#[contracttype]
+#[derive(Debug, Clone)]
+pub struct EntityRecord {
+    pub generation: u32,
+    pub alive: bool,
+    pub component_ids: Vec<u32>,
+}
+
+/// How long ledger entries touched by a [`PersistentWorld`] are kept alive
+///
+/// Mirrors the arguments of `extend_ttl`: once an entry's remaining TTL drops
+/// below `threshold` ledgers it is extended to `extend_to` ledgers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtlPolicy {
+    pub threshold: u32,
+    pub extend_to: u32,
+}
+
+impl TtlPolicy {
+    pub fn new(threshold: u32, extend_to: u32) -> Self {
+        Self {
+            threshold,
+            extend_to,
+        }
+    }
+
+    /// Extend the contract instance, for worlds kept in a single instance entry
+    pub fn extend_instance(&self, env: &Env) {
+        env.storage()
+            .instance()
+            .extend_ttl(self.threshold, self.extend_to);
+    }
+}
+
+/// World backend that keeps every component in its own contract storage entry
+///
+/// Entries are loaded lazily on first access and cached for the rest of the
+/// invocation. Writes only touch the cache and mark the key dirty; call
+/// [`PersistentWorld::flush`] before the contract returns to persist exactly
+/// the modified keys.
+///
+/// Components are keyed by [`ComponentId`]; the names behind those IDs are
+/// stored once, in a single registry entry. Components live in the storage
+/// class given by their [`Durability`]; the registry, entity records and the
+/// allocator are always persistent. With a [`TtlPolicy`] set,
+/// every entry read or written is extended on flush, so entities nobody
+/// touches are left to expire.
+pub struct PersistentWorld {
+    env: Env,
+    registry: ComponentRegistry,
+    ttl_policy: Option<TtlPolicy>,
+    allocator: Option<EntityAllocator>,
+    entities: Map<u64, EntityRecord>,
+    components: Map<(u64, u32), Component>,
+    /// Keys modified since the last flush; `false` marks a deletion
+    dirty: Map<WorldKey, bool>,
+    /// Keys read or written since the last flush
+    touched: Map<WorldKey, ()>,
+}
+
+impl PersistentWorld {
+    /// Open the world stored in the current contract's storage
+    pub fn new(env: &Env) -> Self {
+        let mut world = Self {
+            env: env.clone(),
+            registry: ComponentRegistry::new(),
+            ttl_policy: None,
+            allocator: None,
+            entities: Map::new(env),
+            components: Map::new(env),
+            dirty: Map::new(env),
+            touched: Map::new(env),
+        };
+        // Component IDs must match the ones already on the ledger
+        let entries: Option<Vec<(Symbol, Durability)>> = world.load(&WorldKey::Registry);
+        if let Some(entries) = entries {
+            world.registry.register_entries(&entries);
+            world.touched.set(WorldKey::Registry, ());
+        }
+        world
+    }
+
+    /// The environment this world reads from and writes to
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Extend every touched entry according to `policy` on flush
+    pub fn set_ttl_policy(&mut self, policy: TtlPolicy) {
+        self.ttl_policy = Some(policy);
+    }
+
+    /// Get the TTL policy applied on flush, if any
+    pub fn ttl_policy(&self) -> Option<TtlPolicy> {
+        self.ttl_policy
+    }
+
+    /// Register a typed component so untyped access uses its durability
+    ///
+    /// Returns `None` if the registry is full.
+    pub fn register<T: ComponentTrait>(&mut self) -> Option<ComponentId> {
+        let revision = self.registry.revision();
+        let id = self.registry.register::<T>(&self.env);
+        self.mark_registry(revision);
+        id
+    }
+
+    /// Get the component registry, including durabilities and migrations
+    pub fn registry(&self) -> &ComponentRegistry {
+        &self.registry
+    }
+
+    /// Spawn a new entity with no components
+    pub fn spawn_empty(&mut self) -> EntityId {
+        let mut allocator = self.allocator();
+        let (id, generation) = match allocator.free_list.pop_back() {
+            Some(id) => (id, self.record(id).map(|r| r.generation).unwrap_or(0)),
+            None => {
+                let id = allocator.next_id;
+                allocator.next_id += 1;
+                (id, 0)
+            }
+        };
+        self.set_allocator(allocator);
+
+        let record = EntityRecord {
+            generation,
+            alive: true,
+            component_ids: Vec::new(&self.env),
+        };
+        self.set_record(id, record);
+        EntityId::new(id, generation)
+    }
+
+    /// Spawn a new entity with a bundle of typed components
+    ///
+    /// Panics if the bundle has a new type and the registry is full; use
+    /// `try_spawn_with` to handle that case.
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> EntityId {
+        self.try_spawn_with(bundle)
+            .expect("component registry is full")
+    }
+
+    /// Spawn a new entity with a bundle of typed components
+    ///
+    /// Returns `None`, without spawning, if the bundle has a new type and the
+    /// registry is full.
+    pub fn try_spawn_with<B: Bundle>(&mut self, bundle: B) -> Option<EntityId> {
+        let revision = self.registry.revision();
+        let registered = B::register(&self.env, &mut self.registry);
+        self.mark_registry(revision);
+        if !registered {
+            return None;
+        }
+        let entity_id = self.spawn_empty();
+        for component in bundle.into_components(&self.env).iter() {
+            self.add_component(entity_id, component);
+        }
+        Some(entity_id)
+    }
+
+    /// Despawn an entity, deleting all of its components
+    pub fn despawn(&mut self, entity_id: EntityId) -> bool {
+        let mut record = match self.live_record(entity_id) {
+            Some(record) => record,
+            None => return false,
+        };
+        for component_id in record.component_ids.iter() {
+            self.delete_component(entity_id.id(), ComponentId::new(component_id));
+        }
+        record.generation = record.generation.wrapping_add(1);
+        record.alive = false;
+        record.component_ids = Vec::new(&self.env);
+        self.set_record(entity_id.id(), record);
+
+        let mut allocator = self.allocator();
+        allocator.free_list.push_back(entity_id.id());
+        self.set_allocator(allocator);
+        true
+    }
+
+    /// Check if an entity handle refers to a live entity
+    pub fn exists(&mut self, entity_id: EntityId) -> bool {
+        self.live_record(entity_id).is_some()
+    }
+
+    /// Get the component types attached to an entity
+    pub fn component_types(&mut self, entity_id: EntityId) -> Vec<Symbol> {
+        let mut component_types = Vec::new(&self.env);
+        if let Some(record) = self.live_record(entity_id) {
+            for component_id in record.component_ids.iter() {
+                let component_id = ComponentId::new(component_id);
+                if let Some(component_type) = self.registry.get_component_type(component_id) {
+                    component_types.push_back(component_type);
+                }
+            }
+        }
+        component_types
+    }
+
+    /// Add or replace a component on an entity
+    ///
+    /// Returns `false` if the entity is not alive, or if the component's type
+    /// is new and the registry is full.
+    pub fn add_component(&mut self, entity_id: EntityId, component: Component) -> bool {
+        let mut record = match self.live_record(entity_id) {
+            Some(record) => record,
+            None => return false,
+        };
+        let revision = self.registry.revision();
+        let component_id = match self
+            .registry
+            .register_component(component.component_type().clone())
+        {
+            Some(component_id) => component_id,
+            None => return false,
+        };
+        self.mark_registry(revision);
+        if !record.component_ids.contains(component_id.id()) {
+            record.component_ids.push_back(component_id.id());
+            self.set_record(entity_id.id(), record);
+        }
+        self.components
+            .set((entity_id.id(), component_id.id()), component);
+        self.mark(WorldKey::Component(entity_id.id(), component_id.id()), true);
+        true
+    }
+
+    /// Get a component, loading it from the ledger on first access
+    ///
+    /// Data stored under an outdated schema is upgraded and marked dirty so
+    /// the upgrade is persisted on the next flush. Returns `None` if the
+    /// entry has expired, which can happen for temporary components.
+    pub fn get_component(
+        &mut self,
+        entity_id: EntityId,
+        component_type: &Symbol,
+    ) -> Option<Component> {
+        let component_id = self.registry.get_component_id(component_type)?;
+        let record = self.live_record(entity_id)?;
+        if !record.component_ids.contains(component_id.id()) {
+            return None;
+        }
+        let key = WorldKey::Component(entity_id.id(), component_id.id());
+        let cache_key = (entity_id.id(), component_id.id());
+        let mut component = match self.components.get(cache_key) {
+            Some(component) => component,
+            None => {
+                let component: Component = self.load(&key)?;
+                self.components.set(cache_key, component.clone());
+                self.touched.set(key.clone(), ());
+                component
+            }
+        };
+        if self.registry.migrations().migrate(&mut component) {
+            self.components.set(cache_key, component.clone());
+            self.mark(key, true);
+        }
+        Some(component)
+    }
+
+    /// Remove a component from an entity
+    pub fn remove_component(&mut self, entity_id: EntityId, component_type: &Symbol) -> bool {
+        let component_id = match self.registry.get_component_id(component_type) {
+            Some(component_id) => component_id,
+            None => return false,
+        };
+        let mut record = match self.live_record(entity_id) {
+            Some(record) => record,
+            None => return false,
+        };
+        let index = match record.component_ids.first_index_of(component_id.id()) {
+            Some(index) => index,
+            None => return false,
+        };
+        record.component_ids.remove(index);
+        self.set_record(entity_id.id(), record);
+        self.delete_component(entity_id.id(), component_id);
+        true
+    }
+
+    /// Check if an entity has a component, without loading its data
+    pub fn has_component(&mut self, entity_id: EntityId, component_type: &Symbol) -> bool {
+        let component_id = match self.registry.get_component_id(component_type) {
+            Some(component_id) => component_id,
+            None => return false,
+        };
+        match self.live_record(entity_id) {
+            Some(record) => record.component_ids.contains(component_id.id()),
+            None => false,
+        }
+    }
+
+    /// Insert a typed component, replacing any previous value
+    ///
+    /// Returns `false` if the entity is not alive or the registry is full.
+    pub fn insert<T: ComponentTrait>(&mut self, entity_id: EntityId, value: T) -> bool {
+        self.register::<T>();
+        let component = Component::from_typed(&self.env, &value);
+        self.add_component(entity_id, component)
+    }
+
+    /// Get a typed component
+    pub fn get<T: ComponentTrait>(&mut self, entity_id: EntityId) -> Option<T> {
+        self.register::<T>();
+        let component = self.get_component(entity_id, &T::component_type(&self.env))?;
+        T::deserialize(&self.env, component.data())
+    }
+
+    /// Remove a typed component, returning its last value
+    ///
+    /// Returns `Ok(None)` if the entity did not have the component. The
+    /// component is removed even if its data does not decode as `T`, in
+    /// which case the removed raw data is returned as the error.
+    pub fn remove<T: ComponentTrait>(&mut self, entity_id: EntityId) -> Result<Option<T>, Bytes> {
+        self.register::<T>();
+        let component_type = T::component_type(&self.env);
+        let component = self.get_component(entity_id, &component_type);
+        self.remove_component(entity_id, &component_type);
+        match component {
+            Some(component) => match T::deserialize(&self.env, component.data()) {
+                Some(value) => Ok(Some(value)),
+                None => Err(component.data().clone()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Check if an entity has a typed component
+    pub fn has<T: ComponentTrait>(&mut self, entity_id: EntityId) -> bool {
+        self.has_component(entity_id, &T::component_type(&self.env))
+    }
+
+    /// Register the upgrader that brings stored `T` data up to `T::schema_version()`
+    pub fn register_migration<T: ComponentTrait>(&mut self, migration: Migration) {
+        self.register::<T>();
+        self.registry.register_migration(
+            T::component_type(&self.env),
+            T::schema_version(),
+            migration,
+        );
+    }
+
+    /// Check if there are modifications that have not been flushed
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Get the number of ledger entries the next flush will write or delete
+    pub fn dirty_count(&self) -> u32 {
+        self.dirty.len()
+    }
+
+    /// Write every modified entry to contract storage and delete removed ones
+    ///
+    /// Untouched entries are neither read nor rewritten. If a TTL policy is
+    /// set, every entry read or written since the last flush is extended.
+    pub fn flush(&mut self) {
+        for (key, present) in self.dirty.iter() {
+            if !present {
+                self.delete(&key);
+                continue;
+            }
+            match &key {
+                WorldKey::Allocator => {
+                    if let Some(allocator) = &self.allocator {
+                        self.store(&key, allocator);
+                    }
+                }
+                WorldKey::Registry => self.store(&key, &self.registry.entries(&self.env)),
+                WorldKey::Entity(id) => {
+                    if let Some(record) = self.entities.get(*id) {
+                        self.store(&key, &record);
+                    }
+                }
+                WorldKey::Component(id, component_id) => {
+                    if let Some(component) = self.components.get((*id, *component_id)) {
+                        self.store(&key, &component);
+                    }
+                }
+            }
+        }
+
+        if let Some(policy) = self.ttl_policy {
+            let mut instance_touched = false;
+            for key in self.touched.keys().iter() {
+                if self.dirty.get(key.clone()) == Some(false) {
+                    continue;
+                }
+                let storage = self.env.storage();
+                match self.durability(&key) {
+                    Durability::Persistent => {
+                        storage
+                            .persistent()
+                            .extend_ttl(&key, policy.threshold, policy.extend_to)
+                    }
+                    Durability::Temporary => {
+                        storage
+                            .temporary()
+                            .extend_ttl(&key, policy.threshold, policy.extend_to)
+                    }
+                    Durability::Instance => instance_touched = true,
+                }
+            }
+            if instance_touched {
+                policy.extend_instance(&self.env);
+            }
+        }
+
+        self.dirty = Map::new(&self.env);
+        self.touched = Map::new(&self.env);
+    }
+
+    fn allocator(&mut self) -> EntityAllocator {
+        if let Some(allocator) = &self.allocator {
+            return allocator.clone();
+        }
+        let allocator = match self.load(&WorldKey::Allocator) {
+            Some(allocator) => {
+                self.touched.set(WorldKey::Allocator, ());
+                allocator
+            }
+            None => EntityAllocator {
+                next_id: 1,
+                free_list: Vec::new(&self.env),
+            },
+        };
+        self.allocator = Some(allocator.clone());
+        allocator
+    }
+
+    fn set_allocator(&mut self, allocator: EntityAllocator) {
+        self.allocator = Some(allocator);
+        self.mark(WorldKey::Allocator, true);
+    }
+
+    /// Get an entity record, loading it from the ledger on first access
+    ///
+    /// Temporary components whose entries expired since the record was
+    /// written are dropped from it, so the entity no longer claims them.
+    fn record(&mut self, id: u64) -> Option<EntityRecord> {
+        if let Some(record) = self.entities.get(id) {
+            return Some(record);
+        }
+        let mut record: EntityRecord = self.load(&WorldKey::Entity(id))?;
+        self.touched.set(WorldKey::Entity(id), ());
+        let mut kept = Vec::new(&self.env);
+        for component_id in record.component_ids.iter() {
+            let key = WorldKey::Component(id, component_id);
+            let expired = self.durability(&key) == Durability::Temporary
+                && !self.env.storage().temporary().has(&key);
+            if !expired {
+                kept.push_back(component_id);
+            }
+        }
+        if kept.len() == record.component_ids.len() {
+            self.entities.set(id, record.clone());
+        } else {
+            record.component_ids = kept;
+            self.set_record(id, record.clone());
+        }
+        Some(record)
+    }
+
+    fn live_record(&mut self, entity_id: EntityId) -> Option<EntityRecord> {
+        self.record(entity_id.id())
+            .filter(|record| record.alive && record.generation == entity_id.generation())
+    }
+
+    fn set_record(&mut self, id: u64, record: EntityRecord) {
+        self.entities.set(id, record);
+        self.mark(WorldKey::Entity(id), true);
+    }
+
+    fn delete_component(&mut self, id: u64, component_id: ComponentId) {
+        self.components.remove((id, component_id.id()));
+        self.mark(WorldKey::Component(id, component_id.id()), false);
+    }
+
+    /// Persist the registry if it changed since `revision`
+    fn mark_registry(&mut self, revision: u32) {
+        if self.registry.revision() != revision {
+            self.mark(WorldKey::Registry, true);
+        }
+    }
+
+    fn mark(&mut self, key: WorldKey, present: bool) {
+        self.touched.set(key.clone(), ());
+        self.dirty.set(key, present);
+    }
+
+    /// Storage class a key lives in
+    fn durability(&self, key: &WorldKey) -> Durability {
+        match key {
+            WorldKey::Component(_, component_id) => self
+                .registry
+                .get_component_type(ComponentId::new(*component_id))
+                .map(|component_type| self.registry.durability(&component_type))
+                .unwrap_or_default(),
+            _ => Durability::Persistent,
+        }
+    }
+
+    fn load<V: TryFromVal<Env, Val>>(&self, key: &WorldKey) -> Option<V> {
+        let storage = self.env.storage();
+        match self.durability(key) {
+            Durability::Persistent => storage.persistent().get(key),
+            Durability::Temporary => storage.temporary().get(key),
+            Durability::Instance => storage.instance().get(key),
+        }
+    }
+
+    fn store<V: IntoVal<Env, Val>>(&self, key: &WorldKey, value: &V) {
+        let storage = self.env.storage();
+        match self.durability(key) {
+            Durability::Persistent => storage.persistent().set(key, value),
+            Durability::Temporary => storage.temporary().set(key, value),
+            Durability::Instance => storage.instance().set(key, value),
+        }
+    }
+
+    fn delete(&self, key: &WorldKey) {
+        let storage = self.env.storage();
+        match self.durability(key) {
+            Durability::Persistent => storage.persistent().remove(key),
+            Durability::Temporary => storage.temporary().remove(key),
+            Durability::Instance => storage.instance().remove(key),
+        }
     }
 }
 
-pub type TableStorage = Storage;
-pub type SparseStorage = Storage;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{Position, Velocity};
+    use soroban_sdk::testutils::storage::{Persistent as _, Temporary as _};
+    use soroban_sdk::testutils::Ledger as _;
+    use soroban_sdk::{contract, symbol_short};
+
+    #[contract]
+    struct TestContract;
+
+    #[test]
+    fn test_persistent_world_round_trip() {
+        let env = Env::default();
+        let contract_id = env.register(TestContract, ());
+
+        let (player, bullet) = env.as_contract(&contract_id, || {
+            let mut world = PersistentWorld::new(&env);
+            let player = world.spawn_with((Position::new(1, 2), Velocity::new(0, 1)));
+            let bullet = world.spawn_with(Position::new(5, 5));
+            // Allocator, registry, two entity records and three components
+            assert_eq!(world.dirty_count(), 7);
+            world.flush();
+            assert!(!world.is_dirty());
+            (player, bullet)
+        });
+
+        env.as_contract(&contract_id, || {
+            let storage = env.storage().persistent();
+            let mut world = PersistentWorld::new(&env);
+            let position = world
+                .registry()
+                .get_component_id(&symbol_short!("position"));
+            let position = position.unwrap().id();
+            assert!(storage.has(&WorldKey::Component(player.id(), position)));
+
+            assert_eq!(world.get::<Position>(player).unwrap().y, 2);
+            assert!(world.has::<Velocity>(player));
+            assert!(!world.is_dirty());
+
+            // Only the touched component is rewritten
+            world.insert(player, Position::new(3, 4));
+            assert_eq!(world.dirty_count(), 1);
+
+            // Undecodable data is removed all the same
+            let garbage = Bytes::from_array(&env, &[1]);
+            world.add_component(player, Component::new(symbol_short!("velocity"), garbage));
+            assert!(world.remove::<Velocity>(player).is_err());
+            assert!(!world.has::<Velocity>(player));
+            assert!(matches!(world.remove::<Velocity>(player), Ok(None)));
+
+            assert!(world.despawn(bullet));
+            assert!(!world.exists(bullet));
+            world.flush();
+
+            let bullet_key = WorldKey::Component(bullet.id(), position);
+            assert!(!storage.has(&bullet_key));
+        });
+
+        env.as_contract(&contract_id, || {
+            let mut world = PersistentWorld::new(&env);
+            assert_eq!(world.get::<Position>(player).unwrap().x, 3);
+            assert!(world.get::<Position>(bullet).is_none());
+
+            // The freed slot is reused under a new generation
+            let reused = world.spawn_empty();
+            assert_eq!(reused.id(), bullet.id());
+            assert_ne!(reused.generation(), bullet.generation());
+            assert!(!world.has::<Position>(reused));
+        });
+    }
+
+    #[test]
+    fn test_table_and_sparse_backends() {
+        let env = Env::default();
+        let mut storage = Storage::new(&env);
+        let position = symbol_short!("position");
+        let shield = symbol_short!("shield");
+        let (position_id, shield_id) = (ComponentId::new(1), ComponentId::new(2));
+        let ids = [
+            EntityId::new(1, 0),
+            EntityId::new(2, 0),
+            EntityId::new(3, 0),
+        ];
+        for (i, id) in ids.iter().enumerate() {
+            let data = Bytes::from_array(&env, &[i as u8]);
+            storage.add_component(*id, position_id, Component::new(position.clone(), data));
+        }
+        let data = Bytes::from_array(&env, &[9]);
+        let component = Component::with_storage(shield.clone(), data, ComponentStorage::Sparse);
+        storage.add_component(ids[1], shield_id, component);
+
+        assert_eq!(storage.table.len(), 3);
+        assert_eq!(storage.sparse.len(), 1);
+        assert_eq!(storage.kind(shield_id), Some(ComponentStorage::Sparse));
+        let stored = storage.get_component(ids[1], shield_id).unwrap();
+        assert_eq!(stored.storage(), ComponentStorage::Sparse);
+        assert_eq!(stored.component_type(), &shield);
+
+        // Removing the first row swaps the last one into its place
+        assert!(storage.remove_component(ids[0], position_id));
+        let column = storage.table.entities(position_id);
+        assert_eq!(column.len(), 2);
+        assert_eq!(column.get(0).unwrap(), ids[2]);
+        let moved = storage.get_component(ids[2], position_id).unwrap();
+        assert_eq!(moved.data().get(0), Some(2));
+
+        assert_eq!(storage.remove_entity(ids[1]), 2);
+        assert!(!storage.has_component(ids[1], shield_id));
+        assert_eq!(storage.entities_with(position_id).len(), 1);
+        assert!(storage.sparse.is_empty());
+    }
+
+    #[test]
+    fn test_sparse_sets() {
+        let env = Env::default();
+        let mut sparse = SparseStorage::new(&env);
+        let (shield_id, stun_id) = (ComponentId::new(1), ComponentId::new(2));
+        let ids = [
+            EntityId::new(1, 0),
+            EntityId::new(2, 0),
+            EntityId::new(3, 0),
+        ];
+        for (i, id) in ids.iter().enumerate() {
+            sparse.insert(*id, shield_id, Bytes::from_array(&env, &[i as u8]), 0);
+        }
+        sparse.insert(EntityId::new(4, 0), stun_id, Bytes::new(&env), 2);
+
+        // Each component keeps its own set of holders
+        assert_eq!(sparse.len(), 4);
+        assert_eq!(sparse.sets.get(shield_id.id()).unwrap().dense.len(), 3);
+        assert_eq!(sparse.entities(stun_id).len(), 1);
+        assert_eq!(sparse.get(EntityId::new(4, 0), stun_id).unwrap().1, 2);
+        assert!(!sparse.contains(ids[0], stun_id));
+
+        // Removal swaps the last holder into the hole
+        assert!(sparse.remove(ids[0], shield_id));
+        assert!(!sparse.remove(ids[0], shield_id));
+        let holders = sparse.entities(shield_id);
+        assert_eq!(holders.len(), 2);
+        assert_eq!(holders.get(0).unwrap(), ids[2]);
+        assert_eq!(sparse.get(ids[2], shield_id).unwrap().0.get(0), Some(2));
+        assert_eq!(sparse.get(ids[1], shield_id).unwrap().0.get(0), Some(1));
+
+        // Emptied sets are dropped
+        assert!(sparse.remove(EntityId::new(4, 0), stun_id));
+        assert!(sparse.sets.get(stun_id.id()).is_none());
+        assert_eq!(sparse.len(), 2);
+    }
+
+    /// Short-lived effect kept in temporary storage
+    #[derive(Clone, cougr_derive::Component)]
+    #[component(name = "spark", durability = "temporary")]
+    struct Spark {
+        frames: u32,
+    }
+
+    #[test]
+    fn test_durability_and_ttl_policy() {
+        let env = Env::default();
+        let contract_id = env.register(TestContract, ());
+        let policy = TtlPolicy::new(5_000, 5_000);
+
+        let (player, spark) = env.as_contract(&contract_id, || {
+            let mut world = PersistentWorld::new(&env);
+            world.set_ttl_policy(policy);
+            let player = world.spawn_with(Position::new(0, 0));
+            let spark = world.spawn_with(Spark { frames: 3 });
+            world.flush();
+            (player, spark)
+        });
+
+        // Position is registered first, then the spark
+        let position_key = WorldKey::Component(player.id(), 1);
+        let spark_key = WorldKey::Component(spark.id(), 2);
+        env.as_contract(&contract_id, || {
+            let storage = env.storage();
+            assert!(storage.persistent().has(&position_key));
+            assert!(!storage.persistent().has(&spark_key));
+            assert!(storage.temporary().has(&spark_key));
+            assert_eq!(storage.persistent().get_ttl(&position_key), 5_000);
+            assert_eq!(storage.temporary().get_ttl(&spark_key), 5_000);
+        });
+
+        env.ledger()
+            .with_mut(|ledger| ledger.sequence_number += 1_000);
+
+        env.as_contract(&contract_id, || {
+            let mut world = PersistentWorld::new(&env);
+            world.set_ttl_policy(policy);
+            // Only the player is read, so only its entries are extended
+            assert_eq!(world.get::<Position>(player).unwrap().x, 0);
+            world.flush();
+
+            let storage = env.storage();
+            assert_eq!(storage.persistent().get_ttl(&position_key), 5_000);
+            assert_eq!(storage.temporary().get_ttl(&spark_key), 4_000);
+            assert_eq!(world.get::<Spark>(spark).unwrap().frames, 3);
+        });
+    }
+
+    #[test]
+    fn test_expired_temporary_components() {
+        let env = Env::default();
+        let contract_id = env.register(TestContract, ());
+        let policy = TtlPolicy::new(5_000, 5_000);
+
+        let player = env.as_contract(&contract_id, || {
+            let mut world = PersistentWorld::new(&env);
+            world.set_ttl_policy(policy);
+            let player = world.spawn_with((Position::new(1, 2), Spark { frames: 3 }));
+            world.flush();
+            player
+        });
+
+        // Reading the position keeps the record alive, but not the spark
+        env.ledger()
+            .with_mut(|ledger| ledger.sequence_number += 1_000);
+        env.as_contract(&contract_id, || {
+            let mut world = PersistentWorld::new(&env);
+            world.set_ttl_policy(policy);
+            assert_eq!(world.get::<Position>(player).unwrap().x, 1);
+            world.flush();
+        });
+
+        env.ledger()
+            .with_mut(|ledger| ledger.sequence_number += 4_500);
+        env.as_contract(&contract_id, || {
+            let mut world = PersistentWorld::new(&env);
+            assert!(!world.has::<Spark>(player));
+            assert!(world.get::<Spark>(player).is_none());
+            assert!(world.has::<Position>(player));
+            assert_eq!(world.component_types(player).len(), 1);
+            // The trimmed record is written back
+            assert_eq!(world.dirty_count(), 1);
+            world.flush();
+        });
+    }
+
+    /// Match total kept alongside the contract instance
+    #[derive(Clone, cougr_derive::Component)]
+    #[component(name = "matches", durability = "instance")]
+    struct Matches {
+        played: u32,
+    }
+
+    #[test]
+    fn test_durability_survives_reload() {
+        let env = Env::default();
+        let contract_id = env.register(TestContract, ());
+
+        let (spark, lobby) = env.as_contract(&contract_id, || {
+            let mut world = PersistentWorld::new(&env);
+            let spark = world.spawn_with(Spark { frames: 7 });
+            let lobby = world.spawn_with(Matches { played: 12 });
+            world.flush();
+            (spark, lobby)
+        });
+
+        env.as_contract(&contract_id, || {
+            // Nothing is registered by type, so only the stored registry
+            // knows where these entries live
+            let mut world = PersistentWorld::new(&env);
+            let registry = world.registry();
+            assert_eq!(
+                registry.durability(&symbol_short!("spark")),
+                Durability::Temporary
+            );
+            assert_eq!(
+                registry.durability(&symbol_short!("matches")),
+                Durability::Instance
+            );
+            assert!(!world.is_dirty());
+
+            let spark_data = world.get_component(spark, &symbol_short!("spark"));
+            assert_eq!(
+                Spark::deserialize(&env, spark_data.unwrap().data())
+                    .unwrap()
+                    .frames,
+                7
+            );
+            let lobby_data = world.get_component(lobby, &symbol_short!("matches"));
+            assert_eq!(
+                Matches::deserialize(&env, lobby_data.unwrap().data())
+                    .unwrap()
+                    .played,
+                12
+            );
+
+            // Registering the types again leaves the stored registry as is
+            world.register::<Spark>();
+            world.register::<Matches>();
+            assert!(!world.is_dirty());
+        });
+    }
+
+    /// The previous layout: parallel vectors, linear scans and a full rebuild
+    /// on every removal
+    struct LinearStorage {
+        entity_ids: Vec<(u64, u32)>,
+        component_types: Vec<Symbol>,
+        component_data: Vec<Bytes>,
+    }
+
+    impl LinearStorage {
+        fn add(&mut self, key: (u64, u32), component_type: Symbol, data: Bytes) {
+            self.remove(key, component_type.clone());
+            self.entity_ids.push_back(key);
+            self.component_types.push_back(component_type);
+            self.component_data.push_back(data);
+        }
+
+        fn remove(&mut self, key: (u64, u32), component_type: Symbol) {
+            let env = self.entity_ids.env().clone();
+            let (mut ids, mut types, mut data) = (Vec::new(&env), Vec::new(&env), Vec::new(&env));
+            for i in 0..self.entity_ids.len() {
+                let ctype = self.component_types.get(i).unwrap();
+                if self.entity_ids.get(i).unwrap() != key || ctype != component_type {
+                    ids.push_back(self.entity_ids.get(i).unwrap());
+                    types.push_back(ctype);
+                    data.push_back(self.component_data.get(i).unwrap());
+                }
+            }
+            self.entity_ids = ids;
+            self.component_types = types;
+            self.component_data = data;
+        }
+
+        fn get(&self, key: (u64, u32), component_type: &Symbol) -> Option<Bytes> {
+            for i in 0..self.entity_ids.len() {
+                if self.entity_ids.get(i).unwrap() == key
+                    && self.component_types.get(i).unwrap() == *component_type
+                {
+                    return self.component_data.get(i);
+                }
+            }
+            None
+        }
+    }
+
+    fn cpu_cost(env: &Env, f: impl FnOnce()) -> u64 {
+        let mut budget = env.cost_estimate().budget();
+        budget.reset_unlimited();
+        f();
+        budget.cpu_instruction_cost()
+    }
+
+    #[test]
+    fn test_storage_budget() {
+        const ENTITIES: u64 = 64;
+        let env = Env::default();
+        let position = symbol_short!("position");
+        let data = Bytes::from_array(&env, &[0; 8]);
+
+        let mut linear = LinearStorage {
+            entity_ids: Vec::new(&env),
+            component_types: Vec::new(&env),
+            component_data: Vec::new(&env),
+        };
+        let before = cpu_cost(&env, || {
+            for id in 1..=ENTITIES {
+                linear.add((id, 0), position.clone(), data.clone());
+            }
+            for id in 1..=ENTITIES {
+                assert_eq!(linear.get((id, 0), &position).unwrap(), data);
+            }
+        });
+
+        let mut storage = Storage::new(&env);
+        let position_id = ComponentId::new(1);
+        let after = cpu_cost(&env, || {
+            for id in 1..=ENTITIES {
+                let component = Component::new(position.clone(), data.clone());
+                storage.add_component(EntityId::new(id, 0), position_id, component);
+            }
+            for id in 1..=ENTITIES {
+                let component = storage.get_component(EntityId::new(id, 0), position_id);
+                assert_eq!(component.unwrap().data(), &data);
+            }
+        });
+
+        // Inserting and fetching 64 components costs under a quarter of the
+        // instructions the linear layout needed
+        assert!(after > 0);
+        assert!(after * 4 < before);
+    }
+}
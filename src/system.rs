@@ -1,6 +1,5 @@
-use crate::component::Component;
-use crate::entity::{Entity, EntityId};
-use crate::event::{DamageEvent, EventTrait};
+use crate::entity::EntityId;
+use crate::event::DamageEvent;
 use crate::world::World;
 use soroban_sdk::{symbol_short, Symbol, Vec};
 
@@ -48,16 +47,11 @@ impl Query {
 
     /// Execute the query on a world
     pub fn execute(&self, world: &World) -> Vec<EntityId> {
-        // Convert Vec<Symbol> to &[Symbol] by creating a slice
-        // This is a limitation of the Soroban SDK - we can't easily convert Vec to slice
-        // For now, we'll use a different approach
-        let env = soroban_sdk::Env::default();
-        let mut results = Vec::new(&env);
+        let mut results = Vec::new(world.env());
         for entity in world.iter_entities() {
             let mut has_all_components = true;
-            for i in 0..self.component_types.len() {
-                let ctype = self.component_types.get(i).unwrap();
-                if !entity.has_component(&ctype) {
+            for ctype in self.component_types.iter() {
+                if !world.has_component(entity.id(), &ctype) {
                     has_all_components = false;
                     break;
                 }
@@ -79,10 +73,10 @@ pub struct QueryState {
 impl QueryState {
     /// Create a new query state
     pub fn new(query: Query) -> Self {
-        let env = soroban_sdk::Env::default();
+        let last_results = Vec::new(query.component_types.env());
         Self {
             query,
-            last_results: Vec::new(&env),
+            last_results,
         }
     }
 
@@ -186,8 +180,7 @@ impl SystemParam for QueryParam {
     fn fetch(world: &World) -> Self::Fetch {
         // This is a simplified implementation
         // In a real system, you'd have more sophisticated query execution
-        let env = soroban_sdk::Env::default();
-        Vec::new(&env)
+        Vec::new(world.env())
     }
 
     fn fetch_mut(world: &mut World) -> Self::Fetch {
@@ -205,13 +198,18 @@ impl ResourceParam {
     pub fn new(resource_type: Symbol) -> Self {
         Self { resource_type }
     }
+
+    /// Get the resource type this parameter fetches
+    pub fn resource_type(&self) -> &Symbol {
+        &self.resource_type
+    }
 }
 
 impl SystemParam for ResourceParam {
     type Param = Self;
     type Fetch = Option<crate::resource::Resource>;
 
-    fn fetch(world: &World) -> Self::Fetch {
+    fn fetch(_world: &World) -> Self::Fetch {
         // This is a simplified implementation
         // In a real system, you'd have access to the parameter instance
         None
@@ -235,8 +233,7 @@ impl System for MovementSystem {
         let entities_with_movement =
             world.query_entities(&[symbol_short!("position"), symbol_short!("velocity")]);
 
-        for i in 0..entities_with_movement.len() {
-            let entity_id = entities_with_movement.get(i).unwrap();
+        for _entity_id in entities_with_movement.iter() {
             // In a real implementation, you'd:
             // 1. Get the position and velocity components
             // 2. Update the position based on velocity
@@ -267,10 +264,7 @@ impl System for CollisionSystem {
                     entity_b.id(),
                     symbol_short!("physical"),
                 );
-                let env = soroban_sdk::Env::default();
-                let event_data = collision_event.serialize(&env);
-                let event = crate::event::Event::new(symbol_short!("collision"), event_data);
-                world.send_event(event);
+                world.send(collision_event);
             }
         }
     }
@@ -284,18 +278,13 @@ impl System for HealthSystem {
     type Out = ();
 
     fn run(&mut self, world: &mut World, _input: Self::In) -> Self::Out {
-        let damage_events = world.get_events(&symbol_short!("damage"));
-        let env = soroban_sdk::Env::default();
-        for i in 0..damage_events.len() {
-            let event = damage_events.get(i).unwrap();
-            if let Some(damage_event) = DamageEvent::deserialize(&env, event.data()) {
-                let target_entity = EntityId::new(damage_event.target_entity, 0);
-                // In a real implementation, you'd:
-                // 1. Get the health component from the target entity
-                // 2. Apply the damage
-                // 3. Check if the entity should be destroyed
-                // 4. Update the health component
-            }
+        for damage_event in world.read::<DamageEvent>() {
+            let _target_entity = EntityId::new(damage_event.target_entity, 0);
+            // In a real implementation, you'd:
+            // 1. Get the health component from the target entity
+            // 2. Apply the damage
+            // 3. Check if the entity should be destroyed
+            // 4. Update the health component
         }
     }
 }
@@ -313,7 +302,7 @@ mod tests {
         component_types.push_back(symbol_short!("velocity"));
         let query = Query::new(component_types);
 
-        let world = World::new();
+        let world = World::new(&env);
         let results = query.execute(&world);
         assert_eq!(results.len(), 0);
     }
@@ -326,7 +315,7 @@ mod tests {
         let query = Query::new(component_types);
         let mut query_state = QueryState::new(query);
 
-        let world = World::new();
+        let world = World::new(&env);
         let results = query_state.execute(&world);
         assert_eq!(results.len(), 0);
         assert!(query_state.is_empty());
@@ -334,20 +323,22 @@ mod tests {
 
     #[test]
     fn test_function_system() {
-        let mut system = FunctionSystem::new(|world: &mut World, input: i32| {
+        let env = Env::default();
+        let mut system = FunctionSystem::new(|_world: &mut World, input: i32| {
             // Simple system that just returns the input
             input
         });
 
-        let mut world = World::new();
+        let mut world = World::new(&env);
         let result = system.run(&mut world, 42);
         assert_eq!(result, 42);
     }
 
     #[test]
     fn test_movement_system() {
+        let env = Env::default();
         let mut system = MovementSystem;
-        let mut world = World::new();
+        let mut world = World::new(&env);
 
         // This should run without errors
         system.run(&mut world, ());
@@ -355,8 +346,9 @@ mod tests {
 
     #[test]
     fn test_collision_system() {
+        let env = Env::default();
         let mut system = CollisionSystem;
-        let mut world = World::new();
+        let mut world = World::new(&env);
 
         // This should run without errors
         system.run(&mut world, ());
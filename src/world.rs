@@ -1,70 +1,172 @@
-use crate::component::{Component, ComponentRegistry};
-use crate::entity::{Entity, EntityId, EntityIterator, EntityIteratorMut, EntityManager};
-use crate::event::Event;
-use crate::resource::Resource;
-use crate::storage::Storage;
-use soroban_sdk::{contracttype, Symbol, Vec};
+use crate::archetype::Archetypes;
+use crate::change::ChangeTracker;
+use crate::component::{
+    Bundle, Component, ComponentMask, ComponentRegistry, ComponentTrait, Migration,
+};
+use crate::entity::{
+    Entity, EntityId, EntityIterator, EntityIteratorMut, EntityManager, EntityMut,
+};
+use crate::event::{Event, EventCursor, EventTrait, Events};
+use crate::query::{Query, QueryData, QueryMut};
+use crate::resource::{ResMut, Resource, ResourceMut, ResourceTrait};
+use crate::storage::{ComponentMut, Storage};
+use crate::system::System;
+use alloc::vec::Vec as StdVec;
+use core::cell::RefCell;
+use core::fmt;
+use core::ops::RangeBounds;
+use soroban_sdk::{Bytes, Env, Map, Symbol, Vec};
 
 /// The main ECS world that contains all entities, components, and systems
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct World {
+    /// Environment every collection in this world is allocated on
+    env: Env,
     /// Entity manager for handling entity lifecycle
     pub entities: EntityManager,
     /// Component registry for managing component types
     pub components: ComponentRegistry,
     /// Component storage system
     pub storage: Storage,
-    /// Resources (global state)
-    pub resources: Vec<Resource>,
-    /// Event system
-    pub events: Vec<Event>,
+    /// Entities grouped by their exact component set
+    pub archetypes: Archetypes,
+    /// Versions of entity and component changes, per component type
+    pub changes: ChangeTracker,
+    /// Resources (global state), one per resource type
+    pub resources: Map<Symbol, Resource>,
+    /// Double-buffered event queues, one per event type
+    pub events: StdVec<(Symbol, Events)>,
+    /// Number of completed ticks
+    tick: u64,
+    /// Name of the system `run_system` is running
+    running_system: Option<Symbol>,
+    /// Event cursors of systems run by `run_system`, by system and event type
+    ///
+    /// Readers update them while borrowing the world immutably.
+    system_cursors: RefCell<StdVec<((Symbol, Symbol), EventCursor)>>,
 }
 
 impl World {
-    /// Create a new empty world
-    pub fn new() -> Self {
-        let env = soroban_sdk::Env::default();
+    /// Create a new empty world on the invoking contract's environment
+    pub fn new(env: &Env) -> Self {
         Self {
-            entities: EntityManager::new(),
+            env: env.clone(),
+            entities: EntityManager::new(env),
             components: ComponentRegistry::new(),
-            storage: Storage::new(),
-            resources: Vec::new(&env),
-            events: Vec::new(&env),
+            storage: Storage::new(env),
+            archetypes: Archetypes::new(env),
+            changes: ChangeTracker::new(),
+            resources: Map::new(env),
+            events: StdVec::new(),
+            tick: 0,
+            running_system: None,
+            system_cursors: RefCell::new(StdVec::new()),
         }
     }
 
+    /// Get the environment this world was created on
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
     /// Spawn a new empty entity
     pub fn spawn_empty(&mut self) -> Entity {
         let entity_id = self.entities.spawn();
-        Entity::new(entity_id)
+        self.archetypes.spawn(entity_id);
+        self.changes.spawn(entity_id, ComponentMask::new());
+        Entity::new(&self.env, entity_id)
     }
 
     /// Spawn a new entity with components
+    ///
+    /// Panics if a component type is new and the registry is full; use
+    /// `try_spawn` to handle that case.
     pub fn spawn(&mut self, components: Vec<Component>) -> Entity {
+        self.try_spawn(components)
+            .expect("component registry is full")
+    }
+
+    /// Spawn a new entity with components
+    ///
+    /// Returns `None`, without spawning, if a component type is new and the
+    /// registry is full.
+    pub fn try_spawn(&mut self, components: Vec<Component>) -> Option<Entity> {
+        for component in components.iter() {
+            self.components
+                .register_component(component.component_type().clone())?;
+        }
         let entity_id = self.entities.spawn();
-        let mut entity = Entity::new(entity_id);
+        self.archetypes.spawn(entity_id);
+        self.changes.spawn(entity_id, ComponentMask::new());
 
         // Add components to the entity and storage
         for component in components {
             self.add_component_to_entity(entity_id, component);
         }
 
-        entity
+        self.entities.get_entity(entity_id)
+    }
+
+    /// Spawn a new entity with a bundle of typed components
+    ///
+    /// Panics if the bundle has a new type and the registry is full; use
+    /// `try_spawn_with` to handle that case.
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> Entity {
+        self.try_spawn_with(bundle)
+            .expect("component registry is full")
+    }
+
+    /// Spawn a new entity with a bundle of typed components
+    ///
+    /// Returns `None`, without spawning, if the bundle has a new type and the
+    /// registry is full.
+    pub fn try_spawn_with<B: Bundle>(&mut self, bundle: B) -> Option<Entity> {
+        if !B::register(&self.env, &mut self.components) {
+            return None;
+        }
+        let components = bundle.into_components(&self.env);
+        self.try_spawn(components)
     }
 
-    /// Add a component to an entity
-    pub fn add_component_to_entity(&mut self, entity_id: EntityId, component: Component) {
+    /// Add a component to an entity, replacing any existing component of the same type
+    ///
+    /// The component is stored in the table or sparse backend registered for
+    /// its type; the first component of a new type registers its own kind.
+    /// Returns `false` if the entity does not exist, or if the component's
+    /// type is new and the registry is full.
+    pub fn add_component_to_entity(
+        &mut self,
+        entity_id: EntityId,
+        mut component: Component,
+    ) -> bool {
+        if !self.entities.exists(entity_id) {
+            return false;
+        }
         // Register the component type if not already registered
-        self.components
-            .register_component(component.component_type().clone());
-        // Add component type to entity
+        let component_type = component.component_type().clone();
+        let component_id = match self.components.register_component(component_type.clone()) {
+            Some(component_id) => component_id,
+            None => return false,
+        };
+        match self.components.storage_kind(&component_type) {
+            Some(kind) => component.set_storage(kind),
+            None => self
+                .components
+                .set_storage_kind(component_type.clone(), component.storage()),
+        }
+        // Record the component on the entity and move it to its new archetype
         if let Some(mut entity) = self.entities.get_entity_mut(entity_id) {
-            entity.add_component_type(component.component_type().clone());
-            // Since we can't modify the entity in place, we need to update it
-            // This is a limitation of the Soroban SDK
+            if !entity.has_component(component_id) {
+                entity.add_component(component_id);
+                self.archetypes.add_component(entity_id, component_id);
+            }
         }
         // Store the component data
-        self.storage.add_component(entity_id, component);
+        self.storage
+            .add_component(entity_id, component_id, component);
+        self.changes
+            .record(entity_id, ComponentMask::new().with(component_id.bit()));
+        true
     }
 
     /// Remove a component from an entity
@@ -73,53 +175,154 @@ impl World {
         entity_id: EntityId,
         component_type: &Symbol,
     ) -> bool {
-        // Remove component type from entity
+        let component_id = match self.components.get_component_id(component_type) {
+            Some(component_id) => component_id,
+            None => return false,
+        };
+        // Remove component from entity
         if let Some(mut entity) = self.entities.get_entity_mut(entity_id) {
-            entity.remove_component_type(component_type);
-            // Since we can't modify the entity in place, we need to update it
-            // This is a limitation of the Soroban SDK
+            entity.remove_component(component_id);
         }
         // Remove component data from storage
-        self.storage
-            .remove_component(entity_id, component_type.clone())
+        let removed = self.storage.remove_component(entity_id, component_id);
+        if removed {
+            self.archetypes.remove_component(entity_id, component_id);
+            self.changes
+                .record(entity_id, ComponentMask::new().with(component_id.bit()));
+        }
+        removed
     }
 
     /// Get a component from an entity
+    ///
+    /// Data stored under an outdated schema is upgraded through the registered
+    /// migrations before it is returned; use `migrate_all` to persist upgrades.
     pub fn get_component(&self, entity_id: EntityId, component_type: &Symbol) -> Option<Component> {
-        self.storage
-            .get_component(entity_id, component_type.clone())
+        let component_id = self.components.get_component_id(component_type)?;
+        let mut component = self.storage.get_component(entity_id, component_id)?;
+        self.components.migrations().migrate(&mut component);
+        Some(component)
     }
 
-    /// Get a mutable reference to a component from an entity
+    /// Get a mutable handle to a component from an entity
+    ///
+    /// Changes to the component data are persisted when the guard is dropped,
+    /// and recorded as a change to the component so cached queries filtering
+    /// on its data re-check the entity. A guard dropped unchanged records
+    /// nothing.
     pub fn get_component_mut(
         &mut self,
         entity_id: EntityId,
         component_type: &Symbol,
-    ) -> Option<Component> {
-        // Since we simplified storage, we'll need to implement this differently
-        // For now, return a clone of the component if it exists
-        self.get_component(entity_id, component_type)
+    ) -> Option<ComponentMut<'_>> {
+        let component_id = self.components.get_component_id(component_type)?;
+        let mut component = self
+            .storage
+            .get_component_mut(entity_id, component_id)?
+            .track(&mut self.changes);
+        self.components.migrations().migrate(&mut component);
+        Some(component)
+    }
+
+    /// Modify a component's data in place
+    ///
+    /// Returns `false` if the entity does not have the component.
+    pub fn modify_component<F>(
+        &mut self,
+        entity_id: EntityId,
+        component_type: &Symbol,
+        f: F,
+    ) -> bool
+    where
+        F: FnOnce(&mut Bytes),
+    {
+        match self.get_component_mut(entity_id, component_type) {
+            Some(mut component) => {
+                f(component.data_mut());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Insert a typed component on an entity, replacing any existing value
+    ///
+    /// Returns `false` if the entity does not exist or the registry is full.
+    pub fn insert<T: ComponentTrait>(&mut self, entity_id: EntityId, value: T) -> bool {
+        self.components.register::<T>(&self.env);
+        let component = Component::from_typed(&self.env, &value);
+        self.add_component_to_entity(entity_id, component)
+    }
+
+    /// Get a typed component from an entity
+    ///
+    /// Returns `None` if the component is missing or its data does not decode.
+    pub fn get<T: ComponentTrait>(&self, entity_id: EntityId) -> Option<T> {
+        let component = self.get_component(entity_id, &T::component_type(&self.env))?;
+        T::deserialize(&self.env, component.data())
+    }
+
+    /// Remove a typed component from an entity, returning its last value
+    ///
+    /// Returns `Ok(None)` if the entity did not have the component. The
+    /// component is removed even if its data does not decode as `T`, in
+    /// which case the removed raw data is returned as the error.
+    pub fn remove<T: ComponentTrait>(&mut self, entity_id: EntityId) -> Result<Option<T>, Bytes> {
+        let component_type = T::component_type(&self.env);
+        let component = self.get_component(entity_id, &component_type);
+        self.remove_component_from_entity(entity_id, &component_type);
+        match component {
+            Some(component) => match T::deserialize(&self.env, component.data()) {
+                Some(value) => Ok(Some(value)),
+                None => Err(component.data().clone()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Register the upgrader that brings stored `T` data up to `T::schema_version()`
+    pub fn register_migration<T: ComponentTrait>(&mut self, migration: Migration) {
+        self.components.register_migration(
+            T::component_type(&self.env),
+            T::schema_version(),
+            migration,
+        );
+    }
+
+    /// Eagerly upgrade every stored component to its current schema version
+    ///
+    /// Intended to run once during a contract upgrade. Returns the number of
+    /// components that were rewritten.
+    pub fn migrate_all(&mut self) -> u32 {
+        self.storage.migrate_all(self.components.migrations())
+    }
+
+    /// Check if an entity has a typed component
+    pub fn has<T: ComponentTrait>(&self, entity_id: EntityId) -> bool {
+        self.has_component(entity_id, &T::component_type(&self.env))
     }
 
     /// Check if an entity has a specific component
     pub fn has_component(&self, entity_id: EntityId, component_type: &Symbol) -> bool {
-        if let Some(entity) = self.entities.get_entity(entity_id) {
-            entity.has_component(component_type)
-        } else {
-            false
+        let component_id = match self.components.get_component_id(component_type) {
+            Some(component_id) => component_id,
+            None => return false,
+        };
+        match self.entities.get_entity(entity_id) {
+            Some(entity) => entity.has_component(component_id),
+            None => false,
         }
     }
 
     /// Despawn an entity and remove all its components
     pub fn despawn(&mut self, entity_id: EntityId) -> bool {
-        if let Some(entity) = self.entities.get_entity(entity_id) {
-            // Remove all components from storage
-            let component_types = entity.component_types().clone();
-            for i in 0..component_types.len() {
-                let ctype = component_types.get(i).unwrap();
-                self.storage.remove_component(entity_id, ctype.clone());
-            }
-        }
+        let signature = match self.entities.get_entity(entity_id) {
+            Some(entity) => entity.signature(),
+            None => return false,
+        };
+        self.storage.remove_entity(entity_id);
+        self.archetypes.despawn(entity_id);
+        self.changes.despawn(entity_id, signature);
         self.entities.despawn(entity_id)
     }
 
@@ -143,63 +346,160 @@ impl World {
         self.entities.get_entity(entity_id)
     }
 
-    /// Get a mutable reference to an entity by ID
-    pub fn get_entity_mut(&mut self, entity_id: EntityId) -> Option<Entity> {
+    /// Get a mutable handle to an entity by ID
+    ///
+    /// The handle cannot change the entity's components; use
+    /// `add_component_to_entity` and `remove_component_from_entity`, which
+    /// also update archetypes, storage and change versions.
+    pub fn get_entity_mut(&mut self, entity_id: EntityId) -> Option<EntityMut<'_>> {
         self.entities.get_entity_mut(entity_id)
     }
 
-    /// Add a resource to the world
+    /// Add a resource to the world, replacing any resource of the same type
     pub fn add_resource(&mut self, resource: Resource) {
-        self.resources.push_back(resource);
+        self.resources
+            .set(resource.resource_type().clone(), resource);
     }
 
     /// Get a resource by type
     pub fn get_resource(&self, resource_type: &Symbol) -> Option<Resource> {
-        for i in 0..self.resources.len() {
-            let res = self.resources.get(i).unwrap();
-            if res.resource_type() == resource_type {
-                return Some(res.clone());
-            }
-        }
-        None
+        self.resources.get(resource_type.clone())
     }
 
-    /// Get a mutable reference to a resource by type
-    pub fn get_resource_mut(&mut self, resource_type: &Symbol) -> Option<Resource> {
-        // Since soroban_sdk::Vec doesn't have iter_mut, we'll return a clone
-        self.get_resource(resource_type)
+    /// Get a mutable handle to a resource by type
+    ///
+    /// The resource is written back to the world when the guard is dropped.
+    pub fn get_resource_mut(&mut self, resource_type: &Symbol) -> Option<ResMut<'_>> {
+        let resource = self.resources.get(resource_type.clone())?;
+        Some(ResMut::new(&mut self.resources, resource))
     }
 
     /// Remove a resource from the world
     pub fn remove_resource(&mut self, resource_type: &Symbol) -> Option<Resource> {
-        let mut found = None;
-        let mut new_resources = Vec::new(&soroban_sdk::Env::default());
-        for i in 0..self.resources.len() {
-            let res = self.resources.get(i).unwrap();
-            if res.resource_type() == resource_type {
-                found = Some(res.clone());
-            } else {
-                new_resources.push_back(res.clone());
-            }
-        }
-        if found.is_some() {
-            self.resources = new_resources;
+        let resource = self.resources.get(resource_type.clone())?;
+        self.resources.remove(resource_type.clone());
+        Some(resource)
+    }
+
+    /// Insert a typed resource, replacing any previous value
+    pub fn insert_resource<R: ResourceTrait>(&mut self, value: R) {
+        self.add_resource(Resource::from_typed(&self.env, &value));
+    }
+
+    /// Get a typed resource
+    ///
+    /// Returns `None` if the resource is missing or its data does not decode.
+    pub fn resource<R: ResourceTrait>(&self) -> Option<R> {
+        let resource = self.get_resource(&R::resource_type())?;
+        R::deserialize(&self.env, resource.data())
+    }
+
+    /// Get a mutable handle to a typed resource
+    ///
+    /// Changes are encoded and written back when the guard is dropped.
+    pub fn resource_mut<R: ResourceTrait>(&mut self) -> Option<ResourceMut<'_, R>> {
+        let value = self.resource::<R>()?;
+        Some(ResourceMut::new(&mut self.resources, value))
+    }
+
+    /// Insert the default value of a typed resource unless one is present
+    pub fn init_resource<R: ResourceTrait + Default>(&mut self) {
+        if !self.resources.contains_key(R::resource_type()) {
+            self.insert_resource(R::default());
         }
-        found
+    }
+
+    /// Check if a typed resource is present
+    pub fn has_resource<R: ResourceTrait>(&self) -> bool {
+        self.resources.contains_key(R::resource_type())
     }
 
     /// Send an event
-    pub fn send_event(&mut self, event: Event) {
-        self.events.push_back(event);
+    ///
+    /// The event is stamped with the current ledger and tick.
+    pub fn send_event(&mut self, mut event: Event) -> usize {
+        event.stamp(&self.env, self.tick);
+        let event_type = event.event_type().clone();
+        self.events_mut(&event_type).send(event)
+    }
+
+    /// Encode and send a typed event, returning its ID within its queue
+    ///
+    /// Events that opt in with [`EventTrait::published`] are also published
+    /// as contract events.
+    pub fn send<E: EventTrait>(&mut self, event: E) -> usize {
+        if E::published() {
+            crate::event::publish(&self.env, &event);
+        }
+        self.send_event(Event::new(E::event_type(), event.serialize(&self.env)))
+    }
+
+    /// Iterate over every buffered event of type `E`, oldest first
+    ///
+    /// Events that fail to decode are skipped. Use an
+    /// [`EventReader`](crate::event::EventReader) to only see each event once.
+    pub fn read<E: EventTrait>(&self) -> impl Iterator<Item = E> + '_ {
+        self.read_where(|_| true)
+    }
+
+    /// Iterate over the buffered events of type `E` sent during the ticks in `range`
+    pub fn read_ticks<E, R>(&self, range: R) -> impl Iterator<Item = E> + '_
+    where
+        E: EventTrait,
+        R: RangeBounds<u64> + 'static,
+    {
+        self.read_where(move |event| event.in_ticks(&range))
+    }
+
+    /// Iterate over the buffered events of type `E` sent in the ledgers in `range`
+    pub fn read_ledgers<E, R>(&self, range: R) -> impl Iterator<Item = E> + '_
+    where
+        E: EventTrait,
+        R: RangeBounds<u32> + 'static,
+    {
+        self.read_where(move |event| event.in_ledgers(&range))
+    }
+
+    fn read_where<E: EventTrait>(
+        &self,
+        filter: impl Fn(&Event) -> bool + 'static,
+    ) -> impl Iterator<Item = E> + '_ {
+        self.events(&E::event_type())
+            .into_iter()
+            .flat_map(|events| events.iter())
+            .filter(move |event| filter(event))
+            .filter_map(|event| E::deserialize(&self.env, event.data()))
     }
 
-    /// Get all events of a specific type
+    /// Get the event queue of a specific type, if any event of it was sent
+    pub fn events(&self, event_type: &Symbol) -> Option<&Events> {
+        self.events
+            .iter()
+            .find(|(etype, _)| etype == event_type)
+            .map(|(_, events)| events)
+    }
+
+    /// Get the event queue of a specific type, creating it if needed
+    pub fn events_mut(&mut self, event_type: &Symbol) -> &mut Events {
+        let index = match self
+            .events
+            .iter()
+            .position(|(etype, _)| etype == event_type)
+        {
+            Some(index) => index,
+            None => {
+                self.events.push((event_type.clone(), Events::new()));
+                self.events.len() - 1
+            }
+        };
+        &mut self.events[index].1
+    }
+
+    /// Get all buffered events of a specific type
     pub fn get_events(&self, event_type: &Symbol) -> Vec<Event> {
-        let env = soroban_sdk::Env::default();
-        let mut filtered = Vec::new(&env);
-        for i in 0..self.events.len() {
-            let event = self.events.get(i).unwrap();
-            if event.event_type() == event_type {
+        let mut filtered = Vec::new(&self.env);
+        if let Some(events) = self.events(event_type) {
+            for event in events.iter() {
                 filtered.push_back(event.clone());
             }
         }
@@ -208,50 +508,121 @@ impl World {
 
     /// Clear all events
     pub fn clear_events(&mut self) {
-        let env = soroban_sdk::Env::default();
-        self.events = Vec::new(&env);
+        for (_, events) in self.events.iter_mut() {
+            events.clear();
+        }
+    }
+
+    /// Get the number of completed ticks
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Finish the current tick
+    ///
+    /// Call once per game tick. Rotates every event queue, so events stay
+    /// readable for the tick they were sent in and the one after.
+    pub fn advance_tick(&mut self) -> u64 {
+        for (_, events) in self.events.iter_mut() {
+            events.update();
+        }
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Run a system under `name`
+    ///
+    /// System parameters fetched while it runs, such as an
+    /// [`EventReader`](crate::event::EventReader), keep their state under
+    /// `name` between runs.
+    pub fn run_system<S: System>(&mut self, name: Symbol, system: &mut S, input: S::In) -> S::Out {
+        let outer = self.running_system.replace(name);
+        let out = system.run(self, input);
+        self.running_system = outer;
+        out
+    }
+
+    /// Get the name of the system `run_system` is running, if any
+    pub fn running_system(&self) -> Option<&Symbol> {
+        self.running_system.as_ref()
+    }
+
+    /// Get a system's cursor into the events of one type
+    pub(crate) fn system_cursor(&self, system: &Symbol, event_type: &Symbol) -> EventCursor {
+        self.system_cursors
+            .borrow()
+            .iter()
+            .find(|((name, etype), _)| name == system && etype == event_type)
+            .map(|(_, cursor)| cursor.clone())
+            .unwrap_or_default()
+    }
+
+    /// Save a system's cursor into the events of one type
+    pub(crate) fn set_system_cursor(
+        &self,
+        system: &Symbol,
+        event_type: &Symbol,
+        cursor: EventCursor,
+    ) {
+        let mut cursors = self.system_cursors.borrow_mut();
+        match cursors
+            .iter_mut()
+            .find(|((name, etype), _)| name == system && etype == event_type)
+        {
+            Some((_, saved)) => *saved = cursor,
+            None => cursors.push(((system.clone(), event_type.clone()), cursor)),
+        }
     }
 
     /// Iterate over all entities
-    pub fn iter_entities(&self) -> EntityIterator {
+    pub fn iter_entities(&self) -> EntityIterator<'_> {
         self.entities.iter_entities()
     }
 
     /// Iterate over all entities mutably
-    pub fn iter_entities_mut(&mut self) -> EntityIteratorMut {
+    pub fn iter_entities_mut(&mut self) -> EntityIteratorMut<'_> {
         self.entities.iter_entities_mut()
     }
 
+    /// Iterate over the decoded components of every entity that has them
+    ///
+    /// `world.query::<(EntityId, Position, Velocity)>()` yields one tuple per
+    /// entity; use [`Query::iter`] to add `with`/`without` filters.
+    pub fn query<D: QueryData>(&self) -> impl Iterator<Item = D::Item> + '_ {
+        Query::new(&self.env).iter::<D>(self)
+    }
+
+    /// Decode components for in-place modification
+    ///
+    /// The [`Mut`](crate::query::Mut) components are written back when the
+    /// returned [`QueryMut`] is dropped.
+    pub fn query_mut<D: QueryData>(&mut self) -> QueryMut<'_, D> {
+        Query::new(&self.env).iter_mut::<D>(self)
+    }
+
     /// Query entities with specific components
     pub fn query_entities(&self, component_types: &[Symbol]) -> Vec<EntityId> {
-        let env = soroban_sdk::Env::default();
-        let mut results = Vec::new(&env);
-        for entity in self.iter_entities() {
-            let mut has_all_components = true;
-            for i in 0..component_types.len() {
-                let ctype = &component_types[i];
-                if !entity.has_component(ctype) {
-                    has_all_components = false;
-                    break;
-                }
-            }
-            if has_all_components {
-                results.push_back(entity.id());
+        let mut required = ComponentMask::new();
+        for ctype in component_types {
+            match self.components.bit_index(ctype) {
+                Some(bit) => required = required.with(bit),
+                None => return Vec::new(&self.env),
             }
         }
-        results
+        self.archetypes.query(required, ComponentMask::new())
     }
 
     /// Clear all entities and components
     pub fn clear_entities(&mut self) {
-        self.entities = EntityManager::new();
-        self.storage = Storage::new();
+        self.entities = EntityManager::new(&self.env);
+        self.storage = Storage::new(&self.env);
+        self.archetypes = Archetypes::new(&self.env);
+        self.changes.clear();
     }
 
     /// Clear all resources
     pub fn clear_resources(&mut self) {
-        let env = soroban_sdk::Env::default();
-        self.resources = Vec::new(&env);
+        self.resources = Map::new(&self.env);
     }
 
     /// Clear everything in the world
@@ -262,55 +633,100 @@ impl World {
     }
 }
 
-impl Default for World {
-    fn default() -> Self {
-        Self::new()
+impl fmt::Debug for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("World")
+            .field("entities", &self.entities)
+            .field("components", &self.components)
+            .field("storage", &self.storage)
+            .field("archetypes", &self.archetypes)
+            .field("resources", &self.resources)
+            .field("events", &self.events)
+            .field("tick", &self.tick)
+            .finish_non_exhaustive()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::component::{ComponentStorage, Position, Velocity};
+    use crate::event::{DamageEvent, EventCursor, EventReader};
+    use crate::resource::GameState;
+    use soroban_sdk::testutils::Ledger as _;
     use soroban_sdk::{symbol_short, Env};
 
     #[test]
     fn test_world_creation() {
-        let world = World::new();
+        let env = Env::default();
+        let world = World::new(&env);
         assert_eq!(world.entity_count(), 0);
         assert_eq!(world.component_count(), 0);
     }
 
     #[test]
     fn test_entity_spawn() {
-        let mut world = World::new();
+        let env = Env::default();
+        let mut world = World::new(&env);
         let entity = world.spawn_empty();
         assert_eq!(world.entity_count(), 1);
         assert!(world.exists(entity.id()));
     }
 
-    // TODO: This test requires sharing Env between World and test objects
-    // which is not straightforward with current architecture
-    // #[test]
-    // fn test_component_management() {
-    //     let mut world = World::new();
-    //     let entity_id = world.spawn_empty().id();
-    //     let env = Env::default();
-    //
-    //     let component_type = symbol_short!("test");
-    //     let mut component_data = soroban_sdk::Bytes::new(&env);
-    //     component_data.append(&soroban_sdk::Bytes::from_array(&env, &[1, 2, 3, 4]));
-    //     let component = Component::new(component_type, component_data);
-    //
-    //     world.add_component_to_entity(entity_id, component);
-    //     assert!(world.has_component(entity_id, &symbol_short!("test")));
-    //
-    //     let retrieved_component = world.get_component(entity_id, &symbol_short!("test"));
-    //     assert!(retrieved_component.is_some());
-    // }
+    #[test]
+    fn test_component_management() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let entity_id = world.spawn_empty().id();
+
+        let component_type = symbol_short!("test");
+        let component_data = Bytes::from_array(&env, &[1, 2, 3, 4]);
+        let component = Component::new(component_type, component_data.clone());
+
+        world.add_component_to_entity(entity_id, component);
+        assert!(world.has_component(entity_id, &symbol_short!("test")));
+
+        let retrieved_component = world.get_component(entity_id, &symbol_short!("test"));
+        assert_eq!(retrieved_component.unwrap().data(), &component_data);
+
+        let results = world.query_entities(&[symbol_short!("test")]);
+        assert_eq!(results.len(), 1);
+
+        assert!(world.remove_component_from_entity(entity_id, &symbol_short!("test")));
+        assert!(!world.has_component(entity_id, &symbol_short!("test")));
+    }
+
+    #[test]
+    fn test_component_mut_writes_back() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let entity_id = world.spawn_empty().id();
+        let component = Component::new(symbol_short!("test"), Bytes::from_array(&env, &[1]));
+        world.add_component_to_entity(entity_id, component);
+
+        {
+            let mut component = world
+                .get_component_mut(entity_id, &symbol_short!("test"))
+                .unwrap();
+            component.data_mut().push_back(2);
+        }
+        assert!(
+            world.modify_component(entity_id, &symbol_short!("test"), |data| {
+                data.push_back(3)
+            })
+        );
+
+        let component = world.get_component(entity_id, &symbol_short!("test"));
+        assert_eq!(
+            component.unwrap().data(),
+            &Bytes::from_array(&env, &[1, 2, 3])
+        );
+    }
 
     #[test]
     fn test_entity_despawn() {
-        let mut world = World::new();
+        let env = Env::default();
+        let mut world = World::new(&env);
         let entity_id = world.spawn_empty().id();
         assert_eq!(world.entity_count(), 1);
 
@@ -319,42 +735,321 @@ mod tests {
         assert!(!world.exists(entity_id));
     }
 
-    // TODO: This test requires sharing Env between World and test objects
-    // which is not straightforward with current architecture
-    // #[test]
-    // fn test_resource_management() {
-    //     let mut world = World::new();
-    //     let env = Env::default();
-    //     let resource_type = symbol_short!("testres");
-    //     let mut resource_data = soroban_sdk::Bytes::new(&env);
-    //     resource_data.append(&soroban_sdk::Bytes::from_array(&env, &[1, 2, 3, 4]));
-    //     let resource = Resource::new(resource_type, resource_data);
-    //
-    //     world.add_resource(resource);
-    //     assert!(world.get_resource(&symbol_short!("testres")).is_some());
-    //
-    //     let removed_resource = world.remove_resource(&symbol_short!("testres"));
-    //     assert!(removed_resource.is_some());
-    //     assert!(world.get_resource(&symbol_short!("testres")).is_none());
-    // }
-
-    // TODO: This test requires sharing Env between World and test objects
-    // which is not straightforward with current architecture
-    // #[test]
-    // fn test_event_system() {
-    //     let mut world = World::new();
-    //     let env = Env::default();
-    //     let event_type = symbol_short!("testevent");
-    //     let mut event_data = soroban_sdk::Bytes::new(&env);
-    //     event_data.append(&soroban_sdk::Bytes::from_array(&env, &[1, 2, 3, 4]));
-    //     let event = Event::new(event_type, event_data);
-    //
-    //     world.send_event(event);
-    //     let events = world.get_events(&symbol_short!("testevent"));
-    //     assert_eq!(events.len(), 1);
-    //
-    //     world.clear_events();
-    //     let events = world.get_events(&symbol_short!("testevent"));
-    //     assert_eq!(events.len(), 0);
-    // }
+    #[test]
+    fn test_typed_components() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let entity_id = world
+            .spawn_with((Position::new(1, 2), Velocity::new(3, 4)))
+            .id();
+
+        assert!(world.has::<Position>(entity_id));
+        assert!(world.has::<Velocity>(entity_id));
+        assert_eq!(world.get::<Position>(entity_id).unwrap().x, 1);
+
+        world.insert(entity_id, Position::new(10, 20));
+        let position = world.get::<Position>(entity_id).unwrap();
+        assert_eq!((position.x, position.y), (10, 20));
+
+        let velocity = world.remove::<Velocity>(entity_id).unwrap().unwrap();
+        assert_eq!((velocity.x, velocity.y), (3, 4));
+        assert!(!world.has::<Velocity>(entity_id));
+        assert!(world.get::<Velocity>(entity_id).is_none());
+        assert!(world.remove::<Velocity>(entity_id).unwrap().is_none());
+
+        // Undecodable data is still removed, and reported
+        let garbage = Bytes::from_array(&env, &[1]);
+        world.add_component_to_entity(
+            entity_id,
+            Component::new(symbol_short!("velocity"), garbage.clone()),
+        );
+        assert!(matches!(world.remove::<Velocity>(entity_id), Err(raw) if raw == garbage));
+        assert!(!world.has::<Velocity>(entity_id));
+    }
+
+    #[test]
+    fn test_spawn_with_full_registry() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let position = world.spawn_with(Position::new(1, 2)).id();
+        for i in 1..crate::component::MAX_COMPONENT_TYPES {
+            let name = Symbol::new(&env, &alloc::format!("c{}", i));
+            world.components.register_component(name);
+        }
+
+        // A bundle with a new type spawns nothing
+        assert!(world
+            .try_spawn_with((Position::new(3, 4), Velocity::new(1, 1)))
+            .is_none());
+        assert_eq!(world.entity_count(), 1);
+        let mut components = Vec::new(&env);
+        components.push_back(Component::from_typed(&env, &Velocity::new(1, 1)));
+        assert!(world.try_spawn(components).is_none());
+        assert_eq!(world.entity_count(), 1);
+
+        // Known types still spawn
+        let entity = world.try_spawn_with(Position::new(3, 4)).unwrap();
+        assert_eq!(entity.component_count(), 1);
+        assert_eq!(world.get::<Position>(position).unwrap().x, 1);
+    }
+
+    /// Second schema of a position that gained a `z` coordinate
+    #[derive(Clone, cougr_derive::Component)]
+    #[component(name = "position", version = 1)]
+    struct Position3 {
+        x: i32,
+        y: i32,
+        z: i32,
+    }
+
+    fn add_z(from: u32, data: Bytes) -> Bytes {
+        assert_eq!(from, 0);
+        let mut upgraded = data.clone();
+        upgraded.extend_from_array(&0i32.to_be_bytes());
+        upgraded
+    }
+
+    #[test]
+    fn test_schema_migrations() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let lazy = world.spawn_with(Position::new(1, 2)).id();
+        let eager = world.spawn_with(Position::new(3, 4)).id();
+        assert!(world.get::<Position3>(lazy).is_none());
+
+        // Contract upgrade: the new schema registers its upgrader
+        world.register_migration::<Position3>(add_z);
+
+        let position = world.get::<Position3>(lazy).unwrap();
+        assert_eq!((position.x, position.y, position.z), (1, 2, 0));
+        let component = world.get_component(lazy, &symbol_short!("position"));
+        assert_eq!(component.unwrap().version(), 1);
+
+        assert_eq!(world.migrate_all(), 2);
+        assert_eq!(world.migrate_all(), 0);
+        let position = world
+            .components
+            .get_component_id(&symbol_short!("position"));
+        let stored = world
+            .storage
+            .get_component(eager, position.unwrap())
+            .unwrap();
+        assert_eq!(stored.version(), 1);
+        assert_eq!(stored.data().len(), 12);
+
+        world.insert(eager, Position3 { x: 5, y: 6, z: 7 });
+        assert_eq!(world.get::<Position3>(eager).unwrap().z, 7);
+    }
+
+    /// Component whose name does not fit in a `symbol_short!`
+    #[derive(Clone, cougr_derive::Component)]
+    #[component(name = "projectile_velocity")]
+    struct ProjectileVelocity {
+        speed: u32,
+    }
+
+    #[test]
+    fn test_long_component_names() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let entity = world
+            .spawn_with((Position::new(0, 0), ProjectileVelocity { speed: 9 }))
+            .id();
+
+        let name = Symbol::new(&env, "projectile_velocity");
+        assert_eq!(ProjectileVelocity::component_type(&env), name);
+        let component_id = world.components.get_component_id(&name).unwrap();
+        assert_eq!(component_id.id(), 2);
+        assert!(world.storage.table.contains(entity, component_id));
+        assert!(world.has_component(entity, &name));
+        assert_eq!(world.get::<ProjectileVelocity>(entity).unwrap().speed, 9);
+    }
+
+    #[test]
+    fn test_storage_routing() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let entity = world.spawn_with(Position::new(1, 1)).id();
+        // Untyped components of a registered type follow the registered kind
+        let tag = symbol_short!("tag");
+        world
+            .components
+            .set_storage_kind(tag.clone(), ComponentStorage::Sparse);
+        let data = Bytes::from_array(&env, &[1]);
+        world.add_component_to_entity(entity, Component::new(tag.clone(), data));
+
+        let position = world
+            .components
+            .get_component_id(&symbol_short!("position"));
+        let tag_id = world.components.get_component_id(&tag);
+        assert!(world.storage.table.contains(entity, position.unwrap()));
+        assert!(world.storage.sparse.contains(entity, tag_id.unwrap()));
+        assert_eq!(
+            world.get_component(entity, &tag).unwrap().storage(),
+            ComponentStorage::Sparse
+        );
+    }
+
+    #[test]
+    fn test_stale_entity_handles() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let bytes = Bytes::from_array(&env, &[1]);
+
+        let enemy = world.spawn_empty().id();
+        world.add_component_to_entity(enemy, Component::new(symbol_short!("health"), bytes));
+        assert!(world.despawn(enemy));
+
+        let replacement = world.spawn_empty().id();
+        assert_eq!(replacement.id(), enemy.id());
+        world.add_component_to_entity(
+            replacement,
+            Component::new(symbol_short!("health"), Bytes::from_array(&env, &[2])),
+        );
+
+        assert!(!world.exists(enemy));
+        assert!(world
+            .get_component(enemy, &symbol_short!("health"))
+            .is_none());
+        assert!(!world.despawn(enemy));
+        assert!(world
+            .get_component(replacement, &symbol_short!("health"))
+            .is_some());
+    }
+
+    #[test]
+    fn test_resource_management() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let resource_type = symbol_short!("testres");
+        let resource_data = Bytes::from_array(&env, &[1, 2, 3, 4]);
+        let resource = Resource::new(resource_type, resource_data);
+
+        world.add_resource(resource);
+        assert!(world.get_resource(&symbol_short!("testres")).is_some());
+
+        world
+            .get_resource_mut(&symbol_short!("testres"))
+            .unwrap()
+            .data_mut()
+            .push_back(5);
+        let resource = world.get_resource(&symbol_short!("testres")).unwrap();
+        assert_eq!(resource.data(), &Bytes::from_array(&env, &[1, 2, 3, 4, 5]));
+
+        // Adding the same type again replaces it
+        let resource = Resource::new(symbol_short!("testres"), Bytes::from_array(&env, &[9]));
+        world.add_resource(resource);
+        assert_eq!(world.resources.len(), 1);
+
+        let removed_resource = world.remove_resource(&symbol_short!("testres"));
+        assert_eq!(
+            removed_resource.unwrap().data(),
+            &Bytes::from_array(&env, &[9])
+        );
+        assert!(world.get_resource(&symbol_short!("testres")).is_none());
+    }
+
+    #[test]
+    fn test_typed_resources() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        assert!(world.resource::<GameState>().is_none());
+
+        world.init_resource::<GameState>();
+        assert_eq!(world.resource::<GameState>().unwrap().level, 1);
+
+        world
+            .resource_mut::<GameState>()
+            .unwrap()
+            .increment_score(50);
+        // Already present, so the score is kept
+        world.init_resource::<GameState>();
+        assert_eq!(world.resource::<GameState>().unwrap().score, 50);
+
+        let mut state = GameState::new();
+        state.game_over();
+        world.insert_resource(state);
+        assert_eq!(world.resources.len(), 1);
+        let state = world.resource::<GameState>().unwrap();
+        assert!(state.is_game_over);
+        assert_eq!(state.score, 0);
+    }
+
+    #[test]
+    fn test_event_system() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let event_type = symbol_short!("testevent");
+        let event_data = Bytes::from_array(&env, &[1, 2, 3, 4]);
+        let event = Event::new(event_type, event_data);
+
+        world.send_event(event);
+        let events = world.get_events(&symbol_short!("testevent"));
+        assert_eq!(events.len(), 1);
+
+        world.clear_events();
+        let events = world.get_events(&symbol_short!("testevent"));
+        assert_eq!(events.len(), 0);
+    }
+
+    #[test]
+    fn test_event_stamps() {
+        let env = Env::default();
+        env.ledger().with_mut(|ledger| {
+            ledger.sequence_number = 100;
+            ledger.timestamp = 5_000;
+        });
+        let mut world = World::new(&env);
+        let fire = symbol_short!("fire");
+
+        world.send(DamageEvent::new(1, 10, fire.clone()));
+        world.advance_tick();
+        env.ledger().with_mut(|ledger| ledger.sequence_number = 101);
+        world.send(DamageEvent::new(2, 20, fire.clone()));
+
+        let events = world.get_events(&symbol_short!("damage"));
+        let first = events.get(0).unwrap();
+        assert_eq!(
+            (first.timestamp(), first.ledger(), first.tick()),
+            (5_000, 100, 0)
+        );
+        let second = events.get(1).unwrap();
+        assert_eq!((second.ledger(), second.tick()), (101, 1));
+
+        let in_tick = world.read_ticks::<DamageEvent, _>(1..);
+        assert_eq!(in_tick.map(|e| e.target_entity).sum::<u64>(), 2);
+        let in_ledger = world.read_ledgers::<DamageEvent, _>(..=100);
+        assert_eq!(in_ledger.map(|e| e.target_entity).sum::<u64>(), 1);
+
+        let mut reader = EventReader::<DamageEvent>::new();
+        assert_eq!(reader.read_ticks(&world, 0..1).count(), 1);
+        // The second event was skipped along the way
+        assert!(reader.is_empty(&world));
+    }
+
+    #[test]
+    fn test_event_rotation() {
+        let env = Env::default();
+        let mut world = World::new(&env);
+        let hit = symbol_short!("hit");
+        let mut cursor = EventCursor::default();
+
+        world.send_event(Event::new(hit.clone(), Bytes::new(&env)));
+        world.advance_tick();
+        world.send_event(Event::new(hit.clone(), Bytes::new(&env)));
+        assert_eq!(world.tick(), 1);
+        assert_eq!(world.get_events(&hit).len(), 2);
+
+        let events = world.events(&hit).unwrap();
+        assert_eq!(cursor.read(events).count(), 2);
+
+        // The first event rotates out after its second tick
+        world.advance_tick();
+        assert_eq!(world.get_events(&hit).len(), 1);
+        let events = world.events(&hit).unwrap();
+        assert!(cursor.is_empty(events));
+
+        world.advance_tick();
+        assert_eq!(world.get_events(&hit).len(), 0);
+    }
 }